@@ -19,6 +19,50 @@ function connect() {
       player.play();
     }
 
+    if (msg.type === "DoorState") {
+      let text = msg.state + " (" + msg.position_cm.toFixed(1) + " cm)";
+      if (msg.automation !== "auto") {
+        text += ", radar " + (msg.automation === "manual" ? "off" : "entry only");
+      }
+      if (msg.open_width_cm !== null) {
+        text += ", width " + msg.open_width_cm.toFixed(0) + " cm";
+      }
+      if (msg.hold_remaining_secs !== null) {
+        text += ", closes in " + Math.round(msg.hold_remaining_secs) + " s";
+      }
+      document.getElementById("doorState").textContent = text;
+      document.getElementById("automationSelect").value = msg.automation;
+    }
+
+    if (msg.type === "DoorNotice" && msg.kind === "HoldExpiring") {
+      document.getElementById("status").textContent =
+        "Door closes in " + Math.round(msg.remaining_secs) + " s";
+    }
+
+    if (msg.type === "DoorNotice" && msg.kind === "CalibrationPass") {
+      document.getElementById("status").textContent =
+        "Endstop pass " + msg.pass + "/" + msg.passes;
+    }
+
+    if (msg.type === "DoorNotice" && msg.kind === "CalibrationMeasured") {
+      console.log("Endstops measured", msg.calibration);
+    }
+
+    if (msg.type === "DoorProfile") {
+      const select = document.getElementById("profileSelect");
+      select.replaceChildren(
+        ...Object.keys(msg.profiles).map((name) => new Option(name, name)),
+      );
+      if (msg.name === null) {
+        select.add(new Option("custom", ""));
+      }
+      select.value = msg.name ?? "";
+    }
+
+    if (msg.type === "DoorFault") {
+      console.warn("Door fault", msg);
+    }
+
     if (msg.type === "Targets") {
       points[msg.id] = msg.targets;
       drawCanvas();
@@ -45,6 +89,14 @@ function sendWs(type) {
 
 connect();
 
+function doorCommand(name) {
+  return fetch("/door/" + name, { method: "POST" }).then((res) => {
+    if (!res.ok) {
+      res.json().then((reply) => console.warn(name + ": " + reply.reason));
+    }
+  });
+}
+
 function updatePreview() {
   const r = document.getElementById("rRange").value;
   const g = document.getElementById("gRange").value;
@@ -87,6 +139,18 @@ function sendPlayerTable() {
   ws.send(JSON.stringify(data));
 }
 
+function sendDoorAutomation() {
+  const mode = document.getElementById("automationSelect").value;
+  ws.send(JSON.stringify({ type: "DoorAutomation", mode }));
+}
+
+function sendDoorProfile() {
+  const name = document.getElementById("profileSelect").value;
+  if (name !== "") {
+    ws.send(JSON.stringify({ type: "DoorProfile", name }));
+  }
+}
+
 function toCanvasCoords(x, y) {
   const maxCoord = 4000;
   const scale = Math.min(canvas.width, canvas.height) / maxCoord;
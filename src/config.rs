@@ -0,0 +1,577 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::door::{
+    door::AutomationMode,
+    motion::{MAX_PULSE_FREQ, MAX_RAMP_SECS, ramp_secs},
+    stepper::PulsePerRotation,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub led: LedConfig,
+    pub door: DoorConfig,
+    pub radar: RadarConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub port: u16,
+}
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self { port: 14444 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedConfig {
+    pub count: usize,
+    pub device: String,
+}
+impl Default for LedConfig {
+    fn default() -> Self {
+        Self {
+            count: 150,
+            device: "/dev/spidev0.0".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoorConfig {
+    /// width of an `Open` without a width
+    pub open_distance_cm: f32,
+    /// widest the door may open
+    pub max_open_distance_cm: f32,
+    /// door closes again after this long without an open signal
+    pub cooldown_secs: f32,
+    /// longest hold, also used when a hold comes without a duration
+    pub hold_max_secs: f32,
+    /// a `HoldExpiring` notice goes out this long before a hold ends
+    pub hold_warning_secs: f32,
+    /// endstop positions measured by `MeasureEndstops`, used instead of `endstops.*_step`
+    pub calibration_file: String,
+    /// passes of a `MeasureEndstops` without a count
+    pub calibration_passes: u32,
+    /// passing the middle endstop further off than this is reported as a fault
+    pub drift_threshold_steps: i64,
+    /// input that is pulled low by the emergency stop button
+    pub emergency_stop_pin: Option<u8>,
+    /// JSON lines file with every door event
+    pub audit_log: String,
+    /// JSON file with the wear counters
+    pub stats_file: String,
+    pub startup: StartupPolicy,
+    /// mode after a start, switched at runtime over /door/automation
+    pub automation: AutomationMode,
+    /// written on a clean shutdown for `StartupPolicy::Restore`
+    pub position_file: String,
+    /// named motion profiles to switch between, `stepper.motion` is "default"
+    pub profiles: BTreeMap<String, MotionConfig>,
+    /// daily profile switches, sorted by time
+    pub profile_schedule: Vec<ProfileSwitch>,
+    pub stepper: StepperConfig,
+    pub endstops: EndstopConfig,
+}
+impl DoorConfig {
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_secs_f32(self.cooldown_secs)
+    }
+    pub fn hold_max(&self) -> Duration {
+        Duration::from_secs_f32(self.hold_max_secs)
+    }
+    pub fn hold_warning(&self) -> Duration {
+        Duration::from_secs_f32(self.hold_warning_secs)
+    }
+}
+impl Default for DoorConfig {
+    fn default() -> Self {
+        Self {
+            open_distance_cm: 40.0,
+            max_open_distance_cm: 40.0,
+            cooldown_secs: 5.0,
+            hold_max_secs: 3600.0,
+            hold_warning_secs: 30.0,
+            calibration_file: "door_calibration.json".into(),
+            calibration_passes: 3,
+            drift_threshold_steps: 20,
+            emergency_stop_pin: None,
+            audit_log: "door_audit.jsonl".into(),
+            stats_file: "door_stats.json".into(),
+            startup: StartupPolicy::Idle,
+            automation: AutomationMode::Auto,
+            position_file: "door_position.json".into(),
+            profiles: BTreeMap::new(),
+            profile_schedule: Vec::new(),
+            stepper: StepperConfig::default(),
+            endstops: EndstopConfig::default(),
+        }
+    }
+}
+
+/// Switches to `profile` every day at `at`, "HH:MM" local time
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileSwitch {
+    pub at: String,
+    pub profile: String,
+}
+impl ProfileSwitch {
+    /// minutes since midnight, None when `at` is no time of day
+    pub fn minute(&self) -> Option<u32> {
+        let (h, m) = self.at.split_once(':')?;
+        let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+        (h < 24 && m < 60).then_some(h * 60 + m)
+    }
+}
+
+/// What the door does after a start, its position is unknown until then
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupPolicy {
+    /// calibrate right away
+    Home,
+    /// take the position saved on the last clean shutdown, home without one
+    Restore,
+    /// wait for a calibrate command
+    Idle,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StepperConfig {
+    pub ena_pin: u8,
+    pub dir_pin: u8,
+    pub step_pin: u8,
+    /// pulses per motor turn as set on the driver
+    pub steps_per_rotation: PulsePerRotation,
+    pub wheel_size_cm: f32,
+    /// motor observers follow the position at most this often
+    pub position_update_ms: u64,
+    pub motion: MotionConfig,
+}
+impl StepperConfig {
+    pub fn steps_per_cm(&self) -> f32 {
+        self.steps_per_rotation.pulses() as f32 / self.wheel_size_cm
+    }
+}
+impl Default for StepperConfig {
+    fn default() -> Self {
+        Self {
+            ena_pin: 17,
+            dir_pin: 27,
+            step_pin: 22,
+            steps_per_rotation: PulsePerRotation::PPR1600,
+            wheel_size_cm: 8.0,
+            position_update_ms: 100,
+            motion: MotionConfig::default(),
+        }
+    }
+}
+
+/// Speed limits of `Stepper::turn_to`, also the motion profile that can be
+/// changed at runtime
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotionConfig {
+    /// moves start and end at this speed
+    pub start_speed_cm_s: f32,
+    pub max_speed_cm_s: f32,
+    pub acceleration_cm_s2: f32,
+    /// ramp the acceleration up and down instead of switching it on and off
+    pub s_curve: bool,
+    /// homing and endstop measurement crawl at this speed
+    pub calibration_speed_cm_s: f32,
+}
+impl MotionConfig {
+    /// Broken limits, `steps_per_cm` checks the speeds against what the
+    /// stepper can pulse
+    pub fn problems(&self, steps_per_cm: f32) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !positive(self.start_speed_cm_s) {
+            problems.push("start_speed_cm_s must be positive".to_string());
+        }
+        if self.start_speed_cm_s.partial_cmp(&self.max_speed_cm_s) == Some(Ordering::Greater)
+            || !self.max_speed_cm_s.is_finite()
+        {
+            problems.push("max_speed_cm_s must not be below start_speed_cm_s".to_string());
+        }
+        if !positive(self.acceleration_cm_s2) {
+            problems.push("acceleration_cm_s2 must be positive".to_string());
+        }
+        if !positive(self.calibration_speed_cm_s) {
+            problems.push("calibration_speed_cm_s must be positive".to_string());
+        }
+        // the ramp is precomputed per step, a crawling acceleration would fill memory
+        let ramp = ramp_secs(
+            self.max_speed_cm_s - self.start_speed_cm_s,
+            self.acceleration_cm_s2,
+            self.s_curve,
+        );
+        if positive(self.acceleration_cm_s2) && ramp > MAX_RAMP_SECS {
+            problems.push(format!(
+                "acceleration_cm_s2 must reach max_speed_cm_s within {} s, it takes {:.0} s",
+                MAX_RAMP_SECS, ramp
+            ));
+        }
+        let max_speed = MAX_PULSE_FREQ / steps_per_cm;
+        for (name, speed) in [
+            ("max_speed_cm_s", self.max_speed_cm_s),
+            ("calibration_speed_cm_s", self.calibration_speed_cm_s),
+        ] {
+            if speed > max_speed {
+                problems.push(format!(
+                    "{} must be at most {:.1}, the stepper pulses {} steps/s at most",
+                    name, max_speed, MAX_PULSE_FREQ
+                ));
+            }
+        }
+        problems
+    }
+}
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            start_speed_cm_s: 1.5,
+            max_speed_cm_s: 125.0,
+            acceleration_cm_s2: 540.0,
+            s_curve: false,
+            calibration_speed_cm_s: 0.75,
+        }
+    }
+}
+
+/// Pins of the three endstops and the step count where each switch is released
+/// when the carriage moves in positive direction
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndstopConfig {
+    pub close_pin: u8,
+    pub middle_pin: u8,
+    pub furtherest_pin: u8,
+    pub close_step: i64,
+    pub middle_step: i64,
+    pub furtherest_step: i64,
+}
+impl Default for EndstopConfig {
+    fn default() -> Self {
+        Self {
+            close_pin: 25,
+            middle_pin: 23,
+            furtherest_pin: 24,
+            close_step: 157,
+            middle_step: 3409,
+            furtherest_step: 7722,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RadarConfig {
+    pub uarts: Vec<u8>,
+    /// radars watching the outside, the only ones moving the door in
+    /// `AutomationMode::Entry`
+    pub entry_uarts: Vec<u8>,
+    pub thresholds: Thresholds,
+    pub safety_zone: SafetyZone,
+    pub approach: ApproachWidth,
+}
+impl Default for RadarConfig {
+    fn default() -> Self {
+        Self {
+            uarts: vec![3, 5],
+            entry_uarts: Vec::new(),
+            thresholds: Thresholds::default(),
+            safety_zone: SafetyZone::default(),
+            approach: ApproachWidth::default(),
+        }
+    }
+}
+
+/// Distances in mm used by `Target` to decide about opening and closing
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    /// targets closer than this are alive even when standing still
+    pub near_mm: f32,
+    pub close_min_mm: f32,
+    pub close_max_mm: f32,
+}
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            near_mm: 650.0,
+            close_min_mm: 1000.0,
+            close_max_mm: 1500.0,
+        }
+    }
+}
+
+/// Area in front of the door in radar coordinates (mm). Any target in it
+/// reopens a closing door, whatever `Target` thinks about its intent.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyZone {
+    pub x_min_mm: i16,
+    pub x_max_mm: i16,
+    pub y_min_mm: i16,
+    pub y_max_mm: i16,
+}
+impl SafetyZone {
+    pub fn contains(&self, (x, y): (i16, i16)) -> bool {
+        (self.x_min_mm..=self.x_max_mm).contains(&x) && (self.y_min_mm..=self.y_max_mm).contains(&y)
+    }
+}
+impl Default for SafetyZone {
+    fn default() -> Self {
+        Self {
+            x_min_mm: -600,
+            x_max_mm: 600,
+            y_min_mm: 0,
+            y_max_mm: 1000,
+        }
+    }
+}
+
+/// Width of radar triggered opens, picked from how fast the target comes
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApproachWidth {
+    pub fast_mm_s: f32,
+    pub fast_width_cm: f32,
+}
+impl ApproachWidth {
+    /// None opens to the door default
+    pub fn width_for(&self, speed_mm_s: f32) -> Option<f32> {
+        (speed_mm_s > self.fast_mm_s).then_some(self.fast_width_cm)
+    }
+}
+impl Default for ApproachWidth {
+    fn default() -> Self {
+        Self {
+            fast_mm_s: 1800.0,
+            fast_width_cm: 40.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, io::Error),
+    Parse(String, toml::de::Error),
+    Invalid(Vec<String>),
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read config {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse config {}: {}", path, e),
+            ConfigError::Invalid(problems) => {
+                writeln!(f, "invalid config:")?;
+                for p in problems {
+                    writeln!(f, "  - {}", p)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let name = path.as_ref().display().to_string();
+        let text = fs::read_to_string(&path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+        let config: Config = toml::from_str(&text).map_err(|e| ConfigError::Parse(name, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+    /// Missing default file means defaults, an explicitly given file has to exist
+    pub fn load_or_default<P: AsRef<Path>>(path: P, explicit: bool) -> Result<Self, ConfigError> {
+        if !explicit && !path.as_ref().exists() {
+            println!("No config at {}, using defaults", path.as_ref().display());
+            return Ok(Config::default());
+        }
+        Config::load(path)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let door = &self.door;
+        let stepper = &door.stepper;
+        let endstops = &door.endstops;
+
+        if self.network.port == 0 {
+            problems.push("network.port must not be 0".to_string());
+        }
+        if self.led.count == 0 {
+            problems.push("led.count must be at least 1".to_string());
+        }
+        if !positive(stepper.wheel_size_cm) {
+            problems.push("door.stepper.wheel_size_cm must be positive".to_string());
+        }
+        let steps_per_cm = stepper.steps_per_cm();
+        for p in stepper.motion.problems(steps_per_cm) {
+            problems.push(format!("door.stepper.motion.{}", p));
+        }
+        for (name, motion) in &door.profiles {
+            for p in motion.problems(steps_per_cm) {
+                problems.push(format!("door.profiles.{}.{}", name, p));
+            }
+        }
+        if door.profiles.contains_key("default") {
+            problems.push("door.profiles.default is taken by door.stepper.motion".to_string());
+        }
+        for switch in &door.profile_schedule {
+            if switch.minute().is_none() {
+                problems.push(format!(
+                    "door.profile_schedule at = \"{}\" is no HH:MM time",
+                    switch.at
+                ));
+            }
+            if switch.profile != "default" && !door.profiles.contains_key(&switch.profile) {
+                problems.push(format!(
+                    "door.profile_schedule switches to the unknown profile \"{}\"",
+                    switch.profile
+                ));
+            }
+        }
+        if !positive(door.open_distance_cm) {
+            problems.push("door.open_distance_cm must be positive".to_string());
+        }
+        if door.max_open_distance_cm < door.open_distance_cm {
+            problems
+                .push("door.max_open_distance_cm must not be below open_distance_cm".to_string());
+        }
+        let approach = &self.radar.approach;
+        if !positive(approach.fast_mm_s) {
+            problems.push("radar.approach.fast_mm_s must be positive".to_string());
+        }
+        if !(positive(approach.fast_width_cm)
+            && approach.fast_width_cm <= door.max_open_distance_cm)
+        {
+            problems.push(
+                "radar.approach.fast_width_cm must be positive and at most door.max_open_distance_cm"
+                    .to_string(),
+            );
+        }
+        if !(door.cooldown_secs.is_finite() && door.cooldown_secs >= 0.0) {
+            problems.push("door.cooldown_secs must not be negative".to_string());
+        }
+        if !positive(door.hold_max_secs) {
+            problems.push("door.hold_max_secs must be positive".to_string());
+        }
+        if !(door.hold_warning_secs.is_finite() && door.hold_warning_secs >= 0.0) {
+            problems.push("door.hold_warning_secs must not be negative".to_string());
+        }
+        if door.calibration_passes == 0 {
+            problems.push("door.calibration_passes must be at least 1".to_string());
+        }
+        if door.drift_threshold_steps < 0 {
+            problems.push("door.drift_threshold_steps must not be negative".to_string());
+        }
+        if !(endstops.close_step < endstops.middle_step
+            && endstops.middle_step < endstops.furtherest_step)
+        {
+            problems.push(
+                "door.endstops steps must be ordered close_step < middle_step < furtherest_step"
+                    .to_string(),
+            );
+        }
+
+        let mut pins = vec![
+            ("door.stepper.ena_pin", stepper.ena_pin),
+            ("door.stepper.dir_pin", stepper.dir_pin),
+            ("door.stepper.step_pin", stepper.step_pin),
+            ("door.endstops.close_pin", endstops.close_pin),
+            ("door.endstops.middle_pin", endstops.middle_pin),
+            ("door.endstops.furtherest_pin", endstops.furtherest_pin),
+        ];
+        if let Some(pin) = door.emergency_stop_pin {
+            pins.push(("door.emergency_stop_pin", pin));
+        }
+        let mut seen = HashMap::new();
+        for (name, pin) in pins {
+            if pin > 27 {
+                problems.push(format!("{} = {} is not a BCM gpio pin", name, pin));
+            }
+            if let Some(other) = seen.insert(pin, name) {
+                problems.push(format!("{} = {} is also used by {}", name, pin, other));
+            }
+        }
+
+        if self.radar.uarts.len() != self.radar.uarts.iter().collect::<HashSet<_>>().len() {
+            problems.push("radar.uarts contains duplicates".to_string());
+        }
+        for uart in &self.radar.entry_uarts {
+            if !self.radar.uarts.contains(uart) {
+                problems.push(format!("radar.entry_uarts {} is not in radar.uarts", uart));
+            }
+        }
+        if self.door.automation == AutomationMode::Entry && self.radar.entry_uarts.is_empty() {
+            problems.push("door.automation = \"entry\" needs radar.entry_uarts".to_string());
+        }
+        let t = &self.radar.thresholds;
+        if t.close_min_mm.partial_cmp(&t.close_max_mm) != Some(Ordering::Less) {
+            problems.push("radar.thresholds.close_min_mm must be below close_max_mm".to_string());
+        }
+        if !positive(t.near_mm) {
+            problems.push("radar.thresholds.near_mm must be positive".to_string());
+        }
+
+        let zone = &self.radar.safety_zone;
+        if zone.x_min_mm >= zone.x_max_mm || zone.y_min_mm >= zone.y_max_mm {
+            problems.push("radar.safety_zone minimums must be below the maximums".to_string());
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(problems)),
+        }
+    }
+}
+
+fn positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crawling_acceleration_is_refused() {
+        let steps_per_cm = StepperConfig::default().steps_per_cm();
+        assert!(MotionConfig::default().problems(steps_per_cm).is_empty());
+        let motion = MotionConfig {
+            acceleration_cm_s2: 0.004,
+            ..MotionConfig::default()
+        };
+        let problems = motion.problems(steps_per_cm);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("acceleration_cm_s2"));
+    }
+
+    #[test]
+    fn entry_automation_needs_entry_radars() {
+        let mut config = Config::default();
+        config.door.automation = AutomationMode::Entry;
+        assert!(config.validate().is_err());
+        config.radar.entry_uarts = vec![config.radar.uarts[0]];
+        assert!(config.validate().is_ok(), "{:?}", config.validate());
+    }
+}
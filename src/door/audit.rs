@@ -0,0 +1,354 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem::discriminant,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::door::door::{Event, State};
+
+/// entries kept in memory for queries, the files keep more
+const KEEP: usize = 5000;
+/// the log moves to `<file>.1` at this size, replacing the older one
+const ROTATE_BYTES: u64 = 5 * 1024 * 1024;
+/// end of the log read at startup, enough for `KEEP` entries
+const TAIL_BYTES: u64 = 2 * 1024 * 1024;
+/// the same decision again within this long is not logged
+const REPEAT_MS: u64 = 60_000;
+
+/// Who asked the door to do something
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Source {
+    Radar {
+        uart: u8,
+    },
+    Http {
+        route: String,
+    },
+    Ws,
+    Keypad,
+    /// auto close after the cooldown or a hold
+    Watchdog,
+    /// the emergency stop input
+    Button,
+    /// a move of the door itself ended
+    Motion,
+    /// the startup policy
+    Startup,
+}
+impl Source {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Radar { .. } => "radar",
+            Source::Http { .. } => "http",
+            Source::Ws => "ws",
+            Source::Keypad => "keypad",
+            Source::Watchdog => "watchdog",
+            Source::Button => "button",
+            Source::Motion => "motion",
+            Source::Startup => "startup",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// ms since epoch
+    pub timestamp: u64,
+    pub source: Source,
+    pub event: Event,
+    pub from: State,
+    pub to: State,
+    pub acted: bool,
+    /// set on the event that ended a move
+    pub move_ms: Option<u64>,
+}
+impl AuditEntry {
+    pub fn new(source: Source, event: Event, from: State, to: State, acted: bool) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64;
+        Self {
+            timestamp,
+            source,
+            event,
+            from,
+            to,
+            acted,
+            move_ms: None,
+        }
+    }
+    /// same kind of event from the same source
+    fn same_kind(&self, other: &AuditEntry) -> bool {
+        self.source == other.source && discriminant(&self.event) == discriminant(&other.event)
+    }
+    /// same decision as a recent one of the same kind, radar callbacks
+    /// repeat themselves ten times a second, widths included. A change of
+    /// state is never a repeat.
+    fn repeats(&self, other: &AuditEntry) -> bool {
+        (self.from == self.to || !self.acted)
+            && self.same_kind(other)
+            && self.timestamp.saturating_sub(other.timestamp) < REPEAT_MS
+            && self.from == other.from
+            && self.to == other.to
+            && self.acted == other.acted
+            && self.move_ms.is_none()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// newest entries only, default 100
+    pub limit: Option<usize>,
+    /// `Source::kind`
+    pub source: Option<String>,
+    /// ms since epoch
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub acted: Option<bool>,
+}
+
+type Listener = Box<dyn Fn(&AuditEntry) + Send>;
+
+struct Inner {
+    file: Option<LogFile>,
+    recent: VecDeque<AuditEntry>,
+    listeners: Vec<Listener>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    rotate_at: u64,
+}
+impl LogFile {
+    fn open(path: PathBuf, rotate_at: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            rotate_at,
+        })
+    }
+    fn append(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.rotate_at {
+            let mut old = self.path.clone().into_os_string();
+            old.push(".1");
+            fs::rename(&self.path, old)?;
+            *self = Self::open(self.path.clone(), self.rotate_at)?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// The last `max` bytes of a log, without the line cut in half
+fn read_tail(path: &Path, max: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let start = file.metadata()?.len().saturating_sub(max);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let skip = match start {
+        0 => 0,
+        _ => bytes
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(bytes.len(), |i| i + 1),
+    };
+    Ok(String::from_utf8_lossy(&bytes[skip..]).into_owned())
+}
+
+/// Door events and state transitions, appended to a JSON lines file
+#[derive(Clone)]
+pub struct AuditLog(Arc<Mutex<Inner>>);
+impl AuditLog {
+    /// Loads the tail of an existing log and appends to it
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_rotating(path.as_ref(), ROTATE_BYTES)
+    }
+    fn open_rotating(path: &Path, rotate_at: u64) -> io::Result<Self> {
+        let mut recent = VecDeque::new();
+        if let Ok(text) = read_tail(path, TAIL_BYTES) {
+            for entry in text.lines().filter_map(|l| serde_json::from_str(l).ok()) {
+                if recent.len() == KEEP {
+                    recent.pop_front();
+                }
+                recent.push_back(entry);
+            }
+        }
+        let file = LogFile::open(path.to_path_buf(), rotate_at)?;
+        Ok(Self::with_file(Some(file), recent))
+    }
+    /// Keeps entries in memory only
+    pub fn in_memory() -> Self {
+        Self::with_file(None, VecDeque::new())
+    }
+    fn with_file(file: Option<LogFile>, recent: VecDeque<AuditEntry>) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            file,
+            recent,
+            listeners: Vec::new(),
+        })))
+    }
+
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn(&AuditEntry) + Send + 'static,
+    {
+        self.0.lock().unwrap().listeners.push(Box::new(listener));
+    }
+
+    /// false when the entry repeats a recent one and was dropped
+    pub fn record(&self, entry: AuditEntry) -> bool {
+        let mut inner = self.0.lock().unwrap();
+        let last = inner
+            .recent
+            .iter()
+            .rev()
+            .take(50)
+            .find(|e| entry.same_kind(e));
+        if last.is_some_and(|last| entry.repeats(last)) {
+            return false;
+        }
+        if let Some(file) = &mut inner.file
+            && let Err(e) = file.append(&serde_json::to_string(&entry).unwrap())
+        {
+            println!("Audit log write failed: {}", e);
+        }
+        for l in inner.listeners.iter() {
+            l(&entry);
+        }
+        if inner.recent.len() == KEEP {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(entry);
+        true
+    }
+
+    /// Matching entries, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let inner = self.0.lock().unwrap();
+        let mut entries: Vec<AuditEntry> = inner
+            .recent
+            .iter()
+            .rev()
+            .filter(|e| query.source.as_deref().is_none_or(|s| e.source.kind() == s))
+            .filter(|e| query.since.is_none_or(|t| e.timestamp >= t))
+            .filter(|e| query.until.is_none_or(|t| e.timestamp <= t))
+            .filter(|e| query.acted.is_none_or(|a| e.acted == a))
+            .take(query.limit.unwrap_or(100))
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn radar(event: Event, acted: bool) -> AuditEntry {
+        AuditEntry::new(
+            Source::Radar { uart: 3 },
+            event,
+            State::Opened,
+            State::Opened,
+            acted,
+        )
+    }
+
+    #[test]
+    fn alternating_repeats_are_logged_once() {
+        let audit = AuditLog::in_memory();
+        for i in 0..100 {
+            let width_cm = Some(30.0 + (i % 7) as f32);
+            audit.record(radar(Event::Open { width_cm }, true));
+            audit.record(radar(Event::Obstruction, true));
+        }
+        assert_eq!(audit.query(&AuditQuery::default()).len(), 2);
+
+        // a new decision is logged, as is the same one after a while
+        assert!(audit.record(radar(Event::Close, false)));
+        let mut later = radar(Event::Obstruction, true);
+        later.timestamp += REPEAT_MS;
+        assert!(audit.record(later));
+    }
+
+    #[test]
+    fn state_changes_are_always_logged() {
+        let audit = AuditLog::in_memory();
+        let http = || Source::Http {
+            route: "open".to_string(),
+        };
+        for _ in 0..2 {
+            let open = Event::Open { width_cm: None };
+            let steps = [
+                (http(), open, State::Closed, State::Opening),
+                (Source::Motion, Event::IsOpen, State::Opening, State::Opened),
+                (
+                    Source::Watchdog,
+                    Event::Close,
+                    State::Opened,
+                    State::Closing,
+                ),
+                (
+                    Source::Motion,
+                    Event::IsClose,
+                    State::Closing,
+                    State::Closed,
+                ),
+            ];
+            for (source, event, from, to) in steps {
+                assert!(audit.record(AuditEntry::new(source, event, from, to, true)));
+            }
+        }
+        assert_eq!(audit.query(&AuditQuery::default()).len(), 8);
+    }
+
+    #[test]
+    fn rotates_and_reads_the_tail() {
+        let dir = std::env::temp_dir().join(format!("door-audit-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let audit = AuditLog::open_rotating(&path, 4096).unwrap();
+        for uart in 0..100 {
+            let entry = AuditEntry::new(
+                Source::Radar { uart },
+                Event::Close,
+                State::Opened,
+                State::Closing,
+                true,
+            );
+            assert!(audit.record(entry));
+        }
+        let size = fs::metadata(&path).unwrap().len();
+        assert!(size <= 4096, "{} bytes", size);
+        assert!(dir.join("audit.jsonl.1").exists());
+
+        let reopened = AuditLog::open(&path).unwrap();
+        let entries = reopened.query(&AuditQuery {
+            limit: Some(KEEP),
+            ..Default::default()
+        });
+        assert!(!entries.is_empty());
+        assert_eq!(entries.last().unwrap().source, Source::Radar { uart: 99 });
+
+        fs::write(&path, "first line\nsecond line\nthird line\n").unwrap();
+        assert_eq!(read_tail(&path, 15).unwrap(), "third line\n");
+        assert_eq!(read_tail(&path, 100).unwrap().lines().count(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::EndstopConfig;
+
+/// Steps where the endstops are released when moving positive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EndstopSteps {
+    pub close: i64,
+    pub middle: i64,
+    pub furtherest: i64,
+}
+impl EndstopSteps {
+    pub fn from_config(config: &EndstopConfig) -> Self {
+        Self {
+            close: config.close_step,
+            middle: config.middle_step,
+            furtherest: config.furtherest_step,
+        }
+    }
+}
+
+/// Readings of one switch over all passes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchStats {
+    pub mean: f32,
+    /// max - min
+    pub spread: i64,
+    pub readings: Vec<i64>,
+}
+impl SwitchStats {
+    fn new(readings: Vec<i64>) -> Self {
+        let min = readings.iter().min().copied().unwrap_or(0);
+        let max = readings.iter().max().copied().unwrap_or(0);
+        Self {
+            mean: readings.iter().sum::<i64>() as f32 / readings.len().max(1) as f32,
+            spread: max - min,
+            readings,
+        }
+    }
+}
+
+/// Result of `Door::measure_endstops`, loaded by later calibrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calibration {
+    /// ms since epoch
+    pub measured_at: u64,
+    /// rounded means, used instead of the configured steps
+    pub steps: EndstopSteps,
+    pub close: SwitchStats,
+    pub middle: SwitchStats,
+    pub furtherest: SwitchStats,
+}
+impl Calibration {
+    pub fn from_passes(passes: &[EndstopSteps]) -> Self {
+        let close = SwitchStats::new(passes.iter().map(|p| p.close).collect());
+        let middle = SwitchStats::new(passes.iter().map(|p| p.middle).collect());
+        let furtherest = SwitchStats::new(passes.iter().map(|p| p.furtherest).collect());
+        Self {
+            measured_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_millis() as u64,
+            steps: EndstopSteps {
+                close: close.mean.round() as i64,
+                middle: middle.mean.round() as i64,
+                furtherest: furtherest.mean.round() as i64,
+            },
+            close,
+            middle,
+            furtherest,
+        }
+    }
+
+    /// Why the measurement cannot be used, e.g. a switch that never triggered
+    pub fn problem(&self) -> Option<String> {
+        let s = &self.steps;
+        if !(s.close < s.middle && s.middle < s.furtherest) {
+            return Some(format!(
+                "endstops measured out of order at {} {} {}",
+                s.close, s.middle, s.furtherest
+            ));
+        }
+        None
+    }
+
+    /// None when there is no file yet
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        // written aside and renamed, a power cut leaves the old measurement
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self).unwrap())?;
+        fs::rename(tmp, path)
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender, channel},
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::oneshot;
+
+use crate::door::{
+    audit::{AuditEntry, AuditLog, Source},
+    door::{Door, Event, State},
+    machine::{self, Action, Context, Reply, Target, Transition},
+    monitor::{DoorMonitor, DoorNotice},
+    stats::StatsStore,
+    stepper::{Halt, Retarget},
+};
+
+/// radar frames come every 100 ms, a target stays in the zone a bit longer than its last frame
+const OBSTRUCTION_HOLD: Duration = Duration::from_millis(300);
+
+struct Command {
+    event: Event,
+    source: Source,
+    reply: Option<oneshot::Sender<Reply>>,
+}
+
+enum Job {
+    Move(i64),
+    Calibrate,
+    /// endstop measurement passes
+    MeasureEndstops(u32),
+}
+
+enum Outcome {
+    /// position where the move stopped
+    Moved(i64),
+    Calibrated(bool),
+}
+
+/// door counters that grew during a job
+struct Wear {
+    travel: u64,
+    drift_corrections: u64,
+}
+
+enum Input {
+    Command(Command),
+    Finished(Outcome, Wear),
+}
+
+/// Handle to the controller task started by `start_door_controller`
+#[derive(Clone)]
+pub struct DoorController {
+    tx: Sender<Input>,
+}
+impl DoorController {
+    /// fire and forget, for radar callbacks and buttons
+    pub fn send(&self, event: Event, source: Source) -> bool {
+        self.tx
+            .send(Input::Command(Command {
+                event,
+                source,
+                reply: None,
+            }))
+            .is_ok()
+    }
+    /// Resolves once the controller decided about the event,
+    /// errors when the controller is gone
+    pub fn command(&self, event: Event, source: Source) -> oneshot::Receiver<Reply> {
+        let (reply, rx) = oneshot::channel();
+        let _ = self.tx.send(Input::Command(Command {
+            event,
+            source,
+            reply: Some(reply),
+        }));
+        rx
+    }
+}
+
+/// Owns the state machine. Moves run one at a time on a worker thread that
+/// holds the door, the controller keeps handling events meanwhile and only
+/// touches the move through `Retarget` and `Halt`.
+struct Controller {
+    monitor: DoorMonitor,
+    audit: AuditLog,
+    stats: StatsStore,
+    retarget: Retarget,
+    halt: Halt,
+    jobs: Sender<Job>,
+    /// position of `door.open_distance_cm`
+    default_open: i64,
+    max_open: i64,
+    /// commanded open position, back to the default once the door closed
+    open: i64,
+    cooldown: Duration,
+    moving: bool,
+    move_started: Option<Instant>,
+    /// the running move was turned around or sent elsewhere
+    retargeted: bool,
+    /// reached open since the last close, a close then ends a cycle
+    opened: bool,
+    /// auto close, restarted by every open signal
+    close_at: Option<Instant>,
+    calibration_passes: u32,
+    hold_max: Duration,
+    hold_warning: Duration,
+    hold_until: Option<Instant>,
+    hold_warned: bool,
+    obstructed_at: Option<Instant>,
+    /// level of the emergency stop input, kept by the button thread
+    stop_pressed: Arc<AtomicBool>,
+    /// commands that wait for the running move to end
+    deferred: VecDeque<Command>,
+}
+impl Controller {
+    fn run(mut self, rx: Receiver<Input>) {
+        loop {
+            let input = match self.next_deadline() {
+                Some(at) => match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                    Ok(input) => input,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.deadline_passed();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                },
+                None => match rx.recv() {
+                    Ok(input) => input,
+                    Err(_) => return,
+                },
+            };
+            match input {
+                Input::Command(cmd) => self.handle(cmd),
+                Input::Finished(outcome, wear) => self.finished(outcome, wear),
+            }
+        }
+    }
+
+    fn handle(&mut self, cmd: Command) {
+        self.decide(cmd, None);
+    }
+
+    /// `move_ms` goes to the audit entry of the event that ended a move
+    fn decide(&mut self, cmd: Command, move_ms: Option<u64>) {
+        let state = self.monitor.state();
+        if cmd.event == Event::Obstruction {
+            self.obstructed_at = Some(Instant::now());
+        }
+        let ctx = Context {
+            moving: self.moving,
+            obstructed: self.obstructed(),
+            new_width: self.open_target(&cmd.event) != self.open,
+            stop_pressed: self.stop_pressed.load(Ordering::SeqCst),
+        };
+        let reply = match machine::transition(&state, &cmd.event, ctx)
+            .and_then(|t| self.apply(&state, &cmd.event, t))
+        {
+            Some(reply) => reply,
+            None => {
+                self.deferred.push_back(cmd);
+                return;
+            }
+        };
+        let acted = !matches!(reply, Reply::Rejected(_));
+        if acted {
+            self.monitor.record_event(cmd.event.clone());
+        }
+        let mut entry = AuditEntry::new(
+            cmd.source.clone(),
+            cmd.event.clone(),
+            state.clone(),
+            reply.state().clone(),
+            acted,
+        );
+        entry.move_ms = move_ms;
+        // repeats are left out of the console like of the log
+        if self.audit.record(entry) && acted {
+            println!(
+                "Doorstate: {:?} Doorevent: {:?} -> {:?}",
+                state, cmd.event, reply
+            );
+        }
+        respond(cmd, reply);
+    }
+
+    /// Runs the actions of a transition, None when the running move was
+    /// too far to be retargeted
+    fn apply(&mut self, state: &State, event: &Event, t: Transition) -> Option<Reply> {
+        let open = self.open_target(event);
+        let to = t.to().clone();
+        let mut moves = false;
+        for action in t.actions {
+            match action {
+                Action::Retarget(target) | Action::Reverse(target) => {
+                    let step = match target {
+                        Target::Open => open,
+                        Target::Closed => 0,
+                    };
+                    let set = match action {
+                        Action::Reverse(_) => self.retarget.reverse(step),
+                        _ => self.retarget.set(step),
+                    };
+                    if !set {
+                        return None;
+                    }
+                    self.retargeted = true;
+                    self.open = open;
+                    moves = true;
+                }
+                Action::Move(target) => {
+                    let step = match target {
+                        Target::Open => open,
+                        Target::Closed => 0,
+                    };
+                    self.open = open;
+                    self.start(Job::Move(step));
+                    moves = true;
+                }
+                Action::Calibrate => self.start(Job::Calibrate),
+                Action::MeasureEndstops(passes) => {
+                    let passes = passes.unwrap_or(self.calibration_passes).clamp(1, 50);
+                    self.start(Job::MeasureEndstops(passes));
+                }
+                Action::ArmCooldown => self.arm_cooldown(),
+                Action::CancelCooldown => self.close_at = None,
+                Action::Hold(secs) => {
+                    // the routes refuse invalid durations, should one slip
+                    // through it ends the hold instead of lasting the maximum
+                    let hold = secs.map_or(self.hold_max, |s| {
+                        Duration::try_from_secs_f32(s)
+                            .unwrap_or_default()
+                            .min(self.hold_max)
+                    });
+                    let until = Instant::now() + hold;
+                    self.hold_until = Some(until);
+                    self.hold_warned = false;
+                    self.monitor.set_hold(Some(until));
+                }
+                Action::Halt => self.halt.trigger(),
+                Action::ReleaseHalt => self.halt.release(),
+                Action::RejectDeferred => {
+                    for cmd in self.deferred.drain(..) {
+                        respond(cmd, Reply::Rejected(to.clone()));
+                    }
+                }
+            }
+        }
+        if &to != state || moves {
+            self.set(to);
+        }
+        Some(t.reply)
+    }
+
+    fn finished(&mut self, outcome: Outcome, wear: Wear) {
+        self.moving = false;
+        let took = self.move_started.take().map(|t| t.elapsed());
+        let move_ms = took.map(|t| t.as_millis() as u64);
+        let state = self.monitor.state();
+        let reached = match (&state, &outcome) {
+            _ if self.halt.is_halted() => None,
+            (State::Undefined, Outcome::Calibrated(true)) => Some(Event::Calibrated),
+            (State::Opening, Outcome::Moved(position)) if *position == self.open => {
+                Some(Event::IsOpen)
+            }
+            (State::Closing, Outcome::Moved(0)) => Some(Event::IsClose),
+            _ => None,
+        };
+        let moved = matches!(outcome, Outcome::Moved(_));
+        let cancelled = moved && (self.retargeted || reached.is_none());
+        let cycle = self.opened && reached == Some(Event::IsClose);
+        match reached {
+            Some(Event::IsOpen) => self.opened = true,
+            Some(Event::IsClose) => self.opened = false,
+            _ => {}
+        }
+        self.retargeted = false;
+        self.stats.update(|s| {
+            s.travel_steps += wear.travel;
+            s.drift_corrections += wear.drift_corrections;
+            if moved {
+                s.record_move(move_ms.unwrap_or(0));
+            }
+            s.cancelled_moves += cancelled as u64;
+            s.calibrations += matches!(outcome, Outcome::Calibrated(true)) as u64;
+            s.cycles += cycle as u64;
+        });
+        if let Some(event) = reached {
+            let cmd = Command {
+                event,
+                source: Source::Motion,
+                reply: None,
+            };
+            self.decide(cmd, move_ms);
+        }
+        for cmd in mem::take(&mut self.deferred) {
+            self.handle(cmd);
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let warn_at = self
+            .hold_until
+            .filter(|_| !self.hold_warned)
+            .map(|t| t.checked_sub(self.hold_warning).unwrap_or(t));
+        [self.close_at, warn_at, self.hold_until]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    fn deadline_passed(&mut self) {
+        let now = Instant::now();
+        if let Some(until) = self.hold_until {
+            if now >= until {
+                self.hold_expired();
+            } else if !self.hold_warned && now + self.hold_warning >= until {
+                self.hold_warned = true;
+                self.monitor.notice(DoorNotice::HoldExpiring {
+                    remaining_secs: (until - now).as_secs_f32(),
+                });
+            }
+        }
+        if self.close_at.is_some_and(|at| now >= at) {
+            self.cooldown_expired();
+        }
+    }
+
+    /// Release arms the cooldown, so a blocked close is retried from there
+    fn hold_expired(&mut self) {
+        for event in [Event::Release, Event::Close] {
+            self.handle(Command {
+                event,
+                source: Source::Watchdog,
+                reply: None,
+            });
+        }
+    }
+
+    fn cooldown_expired(&mut self) {
+        self.close_at = None;
+        if self.obstructed() {
+            self.arm_cooldown();
+        } else {
+            self.handle(Command {
+                event: Event::Close,
+                source: Source::Watchdog,
+                reply: None,
+            });
+        }
+    }
+
+    fn start(&mut self, job: Job) {
+        self.moving = true;
+        self.move_started = Some(Instant::now());
+        let _ = self.jobs.send(job);
+    }
+    fn set(&mut self, state: State) {
+        if state != State::Held {
+            self.hold_until = None;
+            self.monitor.set_hold(None);
+        }
+        match state {
+            State::Opening | State::Opened | State::Held => {
+                self.monitor.set_open_target(Some(self.open))
+            }
+            State::Closing => self.monitor.set_open_target(None),
+            _ => {
+                self.open = self.default_open;
+                self.monitor.set_open_target(None);
+            }
+        }
+        self.monitor.set_state(state);
+    }
+    /// Position an open or obstruction heads for, see `Event::Open`
+    fn open_target(&self, event: &Event) -> i64 {
+        match event {
+            Event::Open {
+                width_cm: Some(width),
+            } if width.is_finite() && *width > 0.0 => {
+                ((width / self.monitor.cm_per_step()) as i64).min(self.max_open)
+            }
+            _ => self.open,
+        }
+    }
+    fn arm_cooldown(&mut self) {
+        self.close_at = Some(Instant::now() + self.cooldown);
+    }
+    fn obstructed(&self) -> bool {
+        self.obstructed_at
+            .is_some_and(|at| at.elapsed() < OBSTRUCTION_HOLD)
+    }
+}
+
+fn respond(cmd: Command, reply: Reply) {
+    if let Some(tx) = cmd.reply {
+        let _ = tx.send(reply);
+    }
+}
+
+pub fn start_door_controller(
+    door_arc: Arc<Mutex<Door>>,
+    audit: AuditLog,
+    stats: StatsStore,
+) -> DoorController {
+    let (tx, rx) = channel::<Input>();
+    let (job_tx, job_rx) = channel::<Job>();
+    let handle = DoorController { tx: tx.clone() };
+
+    let stop_pressed = Arc::new(AtomicBool::new(false));
+    let mut controller = {
+        let mut door = door_arc.lock().unwrap();
+        if let Some(pin) = door.take_emergency_input() {
+            let handle = handle.clone();
+            let stop_pressed = stop_pressed.clone();
+            spawn(move || {
+                // button pulls to ground, only the press is an event,
+                // holding it keeps `Reset` from being accepted
+                loop {
+                    let low = pin.is_low();
+                    let was_low = stop_pressed.swap(low, Ordering::SeqCst);
+                    if low && !was_low {
+                        handle.send(Event::EmergencyStop, Source::Button);
+                    }
+                    sleep(Duration::from_millis(5));
+                }
+            });
+        }
+        Controller {
+            monitor: door.get_monitor(),
+            audit,
+            stats,
+            retarget: door.get_retarget(),
+            halt: door.get_halt(),
+            jobs: job_tx,
+            default_open: door.open_position(),
+            max_open: door.max_open_position(),
+            open: door.open_position(),
+            cooldown: door.get_config().cooldown(),
+            moving: false,
+            move_started: None,
+            retargeted: false,
+            opened: false,
+            close_at: None,
+            calibration_passes: door.get_config().calibration_passes,
+            hold_max: door.get_config().hold_max(),
+            hold_warning: door.get_config().hold_warning(),
+            hold_until: None,
+            hold_warned: false,
+            obstructed_at: None,
+            stop_pressed,
+            deferred: VecDeque::new(),
+        }
+    };
+
+    spawn(move || {
+        while let Ok(job) = job_rx.recv() {
+            let mut door = door_arc.lock().unwrap();
+            let (travel, drift_corrections) = (door.get_travel(), door.get_drift_corrections());
+            let outcome = match job {
+                Job::Move(target) => Outcome::Moved(door.move_to(target)),
+                Job::Calibrate => Outcome::Calibrated(door.calibrate()),
+                Job::MeasureEndstops(passes) => Outcome::Calibrated(door.measure_endstops(passes)),
+            };
+            let wear = Wear {
+                travel: door.get_travel() - travel,
+                drift_corrections: door.get_drift_corrections() - drift_corrections,
+            };
+            drop(door);
+            if tx.send(Input::Finished(outcome, wear)).is_err() {
+                return;
+            }
+        }
+    });
+
+    // a restored open door closes like after any other open
+    if controller.monitor.state() == State::Opened {
+        controller.open = controller.monitor.status().steps;
+        controller.set(State::Opened);
+        controller.arm_cooldown();
+    }
+    spawn(move || controller.run(rx));
+
+    handle
+}
@@ -4,15 +4,35 @@ use std::{
     fs::{File, OpenOptions},
     io::Read,
     os::unix::net::UnixStream,
+    sync::{Mutex, OnceLock},
     thread::spawn,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use csv::Writer;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Thresholds;
 
 const SIZE: usize = 5;
 
+// shared by all detector threads so the header is only written once
+static RECORDER: OnceLock<Mutex<Writer<File>>> = OnceLock::new();
+
+fn recorder() -> &'static Mutex<Writer<File>> {
+    RECORDER.get_or_init(|| {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open("data.csv")
+            .unwrap();
+        let wtr = csv::WriterBuilder::new()
+            .has_headers(file.metadata().unwrap().len() == 0)
+            .from_writer(file);
+        Mutex::new(wtr)
+    })
+}
+
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct Target {
     points: [(i16, i16); SIZE],
@@ -28,9 +48,25 @@ pub struct Target {
     is_open_door: bool,
     is_close_door: bool,
     opening_angle: f32,
+    #[serde(skip)]
+    thresholds: Thresholds,
 }
 impl Target {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            ..Default::default()
+        }
+    }
     pub fn update(&mut self, x: i16, y: i16, speed: i16, resolution: u16) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+        self.update_at(x, y, speed, resolution, now);
+    }
+    /// like `update` but with the frame time in ms since epoch, used for replays
+    pub fn update_at(&mut self, x: i16, y: i16, speed: i16, resolution: u16, timestamp: u128) {
         self.speeds.rotate_right(1);
         self.speeds[0] = speed;
 
@@ -52,10 +88,7 @@ impl Target {
 
         let t1 = self.timestamps[0];
         self.timestamps.rotate_right(1);
-        self.timestamps[0] = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis();
+        self.timestamps[0] = timestamp;
         let t_diff = self.timestamps[0] - t1;
         self.calc_speeds.rotate_right(1);
         self.calc_speeds[0] =
@@ -69,8 +102,9 @@ impl Target {
             self.vecs[0].1 as f32 / (t_diff as f32 / 1000.0) * fac,
         );
 
+        let th = self.thresholds;
         self.is_alive =
-            (x, y) != (0, 0) && (self.speeds[0].abs() > 12 || self.distances[0] < 650.0);
+            (x, y) != (0, 0) && (self.speeds[0].abs() > 12 || self.distances[0] < th.near_mm);
 
         // self.is_open_door = self
         //     .angles
@@ -90,13 +124,11 @@ impl Target {
                 .iter()
                 .take(3)
                 .all(|&x| x >= 180.0 - self.opening_angle)
-            || self.distances[0] < 650.0;
-        self.is_close_door = self
-            .calc_speeds
-            .iter()
-            .take(1)
-            .all(|&x| 1000.0 < self.distances[0] && self.distances[0] < 1500.0)
-            && self.angles.iter().take(1).all(|&x| x <= self.opening_angle)
+            || self.distances[0] < th.near_mm;
+        self.is_close_door =
+            self.calc_speeds.iter().take(1).all(|&x| {
+                th.close_min_mm < self.distances[0] && self.distances[0] < th.close_max_mm
+            }) && self.angles.iter().take(1).all(|&x| x <= self.opening_angle)
     }
     pub fn get_points(&self) -> [(i16, i16); SIZE] {
         self.points
@@ -104,6 +136,17 @@ impl Target {
     pub fn get_vecs(&self) -> [(i16, i16); SIZE] {
         self.vecs
     }
+    /// the radar reports this target in the latest frame
+    pub fn is_present(&self) -> bool {
+        self.points[0] != (0, 0)
+    }
+    /// mm/s over the last frame
+    pub fn approach_speed(&self) -> f32 {
+        match self.calc_speeds[0].is_finite() {
+            true => self.calc_speeds[0],
+            false => 0.0,
+        }
+    }
     pub fn is_alive(&self) -> bool {
         self.is_alive
     }
@@ -129,9 +172,7 @@ impl Target {
         let cos_theta = cos_theta.clamp(-1.0, 1.0);
 
         let angle_radians = cos_theta.acos();
-        let angle_degrees = angle_radians * (180.0 / PI);
-
-        angle_degrees
+        angle_radians * (180.0 / PI)
     }
 
     pub fn is_close_door(&self) -> bool {
@@ -144,43 +185,116 @@ fn parse_ld2450_value(low: u8, high: u8) -> i16 {
     let val = (((high & 0x7F) as i16) << 8) | (low as i16);
     if (high & 0x80) == 0 { -val } else { val }
 }
+pub fn encode_ld2450_value(value: i16) -> (u8, u8) {
+    // Gegenstück zu parse_ld2450_value, gesetztes 0x80 Bit heißt positiv
+    let abs = value.unsigned_abs().min(0x7FFF);
+    let sign = if value >= 0 { 0x80 } else { 0x00 };
+    ((abs & 0xFF) as u8, ((abs >> 8) as u8) | sign)
+}
+pub fn socket_path(uart_num: u8) -> String {
+    format!("/tmp/ld2450_{}.sock", uart_num)
+}
+
+/// One radar frame as it is stored in `data.csv`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadarRecord {
+    pub timestamp: u64,
+    pub uart: u8,
+    pub x0: i16,
+    pub y0: i16,
+    pub speed0: i16,
+    pub res0: u16,
+    pub x1: i16,
+    pub y1: i16,
+    pub speed1: i16,
+    pub res1: u16,
+    pub x2: i16,
+    pub y2: i16,
+    pub speed2: i16,
+    pub res2: u16,
+}
+impl RadarRecord {
+    pub fn new(timestamp: u64, uart: u8, raw: [(i16, i16, i16, u16); 3]) -> Self {
+        let [
+            (x0, y0, speed0, res0),
+            (x1, y1, speed1, res1),
+            (x2, y2, speed2, res2),
+        ] = raw;
+        Self {
+            timestamp,
+            uart,
+            x0,
+            y0,
+            speed0,
+            res0,
+            x1,
+            y1,
+            speed1,
+            res1,
+            x2,
+            y2,
+            speed2,
+            res2,
+        }
+    }
+    pub fn raw(&self) -> [(i16, i16, i16, u16); 3] {
+        [
+            (self.x0, self.y0, self.speed0, self.res0),
+            (self.x1, self.y1, self.speed1, self.res1),
+            (self.x2, self.y2, self.speed2, self.res2),
+        ]
+    }
+}
+
+pub fn parse_frame(buffer: &[u8; 30]) -> Option<[(i16, i16, i16, u16); 3]> {
+    if buffer[28] != 0x55 || buffer[29] != 0xCC {
+        return None;
+    }
+    let mut raw = [(0, 0, 0, 0); 3];
+    for (i, target) in raw.iter_mut().enumerate() {
+        let offset = 4 + (i * 8);
+        let x = parse_ld2450_value(buffer[offset], buffer[offset + 1]);
+        let y = parse_ld2450_value(buffer[offset + 2], buffer[offset + 3]);
+        let speed = parse_ld2450_value(buffer[offset + 4], buffer[offset + 5]);
+        let res = ((buffer[offset + 7] as u16) << 8) | (buffer[offset + 6] as u16);
+        *target = (x, y, speed, res);
+    }
+    Some(raw)
+}
 
 pub struct Detector {}
 
 impl Detector {
-    pub fn start<F>(uart_num: u8, mut callback: F)
+    /// `record` appends every frame to `data.csv`
+    pub fn start<F>(uart_num: u8, thresholds: Thresholds, record: bool, mut callback: F)
     where
         F: FnMut([Target; 3]) + Send + 'static,
     {
-        let socket_path = format!("/tmp/ld2450_{}.sock", uart_num);
+        let socket_path = socket_path(uart_num);
 
         spawn(move || {
-            let file = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open("data.csv")
-                .unwrap();
-
-            let mut wtr = csv::WriterBuilder::new()
-                .has_headers(file.metadata().unwrap().len() == 0)
-                .from_writer(file);
-
             let mut stream =
                 UnixStream::connect(socket_path).expect("Socket-Verbindung fehlgeschlagen");
             let mut buffer = [0u8; 30];
-            let mut targets_array: [Target; 3] =
-                [Target::default(), Target::default(), Target::default()];
+            let mut targets_array: [Target; 3] = [
+                Target::new(thresholds),
+                Target::new(thresholds),
+                Target::new(thresholds),
+            ];
             loop {
                 if stream.read_exact(&mut buffer).is_ok() {
-                    if buffer[28] == 0x55 && buffer[29] == 0xCC {
-                        for i in 0..3 {
-                            let offset = 4 + (i * 8);
-                            let x = parse_ld2450_value(buffer[offset], buffer[offset + 1]);
-                            let y = parse_ld2450_value(buffer[offset + 2], buffer[offset + 3]);
-                            let speed = parse_ld2450_value(buffer[offset + 4], buffer[offset + 5]);
-                            let res =
-                                ((buffer[offset + 7] as u16) << 8) | (buffer[offset + 6] as u16);
-                            targets_array[i].update(x, y, speed, res);
+                    if let Some(raw) = parse_frame(&buffer) {
+                        let now = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .expect("Time went backwards")
+                            .as_millis();
+                        for (target, (x, y, speed, res)) in targets_array.iter_mut().zip(raw) {
+                            target.update_at(x, y, speed, res, now);
+                        }
+                        if record {
+                            let mut wtr = recorder().lock().unwrap();
+                            let _ = wtr.serialize(RadarRecord::new(now as u64, uart_num, raw));
+                            let _ = wtr.flush();
                         }
                     }
                     // dbg!(&targets_array);
@@ -190,3 +304,19 @@ impl Detector {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_values_parse_back() {
+        for value in [-4000, -300, -1, 0, 1, 255, 256, 1200, 0x7FFF, -0x7FFF] {
+            let (low, high) = encode_ld2450_value(value);
+            assert_eq!(parse_ld2450_value(low, high), value);
+        }
+        // the radar has 15 bits and a sign, -0x8000 does not fit
+        let (low, high) = encode_ld2450_value(i16::MIN);
+        assert_eq!(parse_ld2450_value(low, high), -0x7FFF);
+    }
+}
@@ -1,20 +1,22 @@
-const DOOR_COOLDOWN: Duration = Duration::from_secs(5);
-use std::{
-    collections::VecDeque,
-    sync::{
-        Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
-        mpsc::{Sender, channel},
-    },
-    thread::{JoinHandle, spawn},
-    time::Duration,
-};
+use std::sync::{Arc, Mutex, atomic::AtomicBool};
 
-use rppal::gpio::Gpio;
+use serde::{Deserialize, Serialize};
 
-use crate::door::stepper::Stepper;
+use crate::{
+    config::DoorConfig,
+    door::{
+        calibration::{Calibration, EndstopSteps},
+        monitor::{DoorFault, DoorMonitor, DoorNotice},
+        motion::MotionPlan,
+        motor::MotorObserver,
+        position::SavedPosition,
+        profile::MotionProfiles,
+        stepper::{Halt, Retarget, Stepper, SyncPoint},
+    },
+    hal::{Error, Hal, InputPin, Pull},
+};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum State {
     Opened,
     Closed,
@@ -23,234 +25,365 @@ pub enum State {
     Held,
     Locked,
     Undefined,
+    /// driver is off, needs `Reset` and a calibration
+    EmergencyStop,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
-    Open,
+    /// opens to `width_cm`, capped at `door.max_open_distance_cm`. Without a
+    /// width a closed door opens to `door.open_distance_cm` and an open one
+    /// keeps its width.
+    Open {
+        width_cm: Option<f32>,
+    },
     Close,
-    Hold,
+    /// keeps the door open for `secs`, at most `door.hold_max_secs`
+    Hold {
+        secs: Option<f32>,
+    },
     Release,
     IsOpen,
     IsClose,
     Lock,
     Unlock,
     Calibrate,
+    /// passes all endstops, stores their positions and calibrates with them
+    MeasureEndstops {
+        passes: Option<u32>,
+    },
+    /// the endstops were found, the door closes from the middle switch
+    Calibrated,
+    /// a radar target is in the safety zone
+    Obstruction,
+    EmergencyStop,
+    /// leaves `EmergencyStop`, the position is unknown afterwards
+    Reset,
+}
+impl Event {
+    /// What is wrong with the parameters, the routes and WS refuse such events
+    pub fn problem(&self) -> Option<&'static str> {
+        let positive = |v: &Option<f32>| v.is_none_or(|v| v.is_finite() && v > 0.0);
+        match self {
+            Event::Open { width_cm } if !positive(width_cm) => {
+                Some("width_cm must be a positive number of cm")
+            }
+            Event::Hold { secs } if !positive(secs) => {
+                Some("secs must be a positive number of seconds")
+            }
+            _ => None,
+        }
+    }
+}
+impl State {
+    /// Whether `event` has an effect in this state. Hold and Lock are also
+    /// taken during the move that ends in the state they need. The ends of
+    /// moves only come from the controller.
+    pub fn accepts(&self, event: &Event) -> bool {
+        use Event::*;
+        use State::*;
+        match (self, event) {
+            (_, Event::EmergencyStop) => true,
+            (State::EmergencyStop, Reset) => true,
+            (State::EmergencyStop, _) => false,
+            (Locked | Undefined, Open { .. }) => false,
+            (_, Open { .. }) => true,
+            (Opened | Opening | Closing, Close) => true,
+            (Opened | Opening | Held, Hold { .. }) => true,
+            (Held, Release) => true,
+            (Closed | Closing, Lock) => true,
+            (Locked, Unlock) => true,
+            (_, Calibrate | MeasureEndstops { .. }) => true,
+            (Opened | Closing, Obstruction) => true,
+            (Opening, IsOpen) | (Closing, IsClose) | (Undefined, Calibrated) => true,
+            (_, _) => false,
+        }
+    }
+}
+/// Which radar signals may move the door, the safety zone works in every mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutomationMode {
+    /// every radar opens and closes the door
+    Auto,
+    /// only commands open and close, e.g. while cleaning
+    Manual,
+    /// only the radars in `radar.entry_uarts` open and close
+    Entry,
+}
+impl AutomationMode {
+    /// Whether targets of a radar may open or close the door
+    pub fn radar_allowed(&self, entry_radar: bool) -> bool {
+        match self {
+            AutomationMode::Auto => true,
+            AutomationMode::Manual => false,
+            AutomationMode::Entry => entry_radar,
+        }
+    }
+}
+
+struct Endstops {
+    close: Box<dyn InputPin>,
+    middle: Box<dyn InputPin>,
+    furtherest: Box<dyn InputPin>,
 }
 pub struct Door {
+    config: DoorConfig,
     state: Arc<Mutex<State>>,
+    monitor: DoorMonitor,
     stepper: Stepper,
+    endstops: Endstops,
+    /// from the calibration file, the config otherwise
+    endstop_steps: EndstopSteps,
     stepper_cancler: Arc<AtomicBool>,
-    door_dog: Option<Sender<()>>,
+    retarget: Retarget,
+    halt: Halt,
+    emergency_input: Option<Box<dyn InputPin>>,
+    /// re-syncs at the middle endstop that changed the counter
+    drift_corrections: u64,
+    profiles: MotionProfiles,
 }
 impl Door {
-    pub fn new() -> Arc<Mutex<Self>> {
-        let lop = Stepper::new(17, 27, 22, 1600, 8.0).unwrap();
+    pub fn new(hal: &dyn Hal, config: DoorConfig) -> Result<Arc<Mutex<Self>>, Error> {
+        let lop = Stepper::new(hal, &config.stepper)?;
+        let pins = &config.endstops;
+        let state = Arc::new(Mutex::new(State::Undefined));
+        let monitor = DoorMonitor::new(
+            state.clone(),
+            lop.get_step_counter_clone(),
+            lop.get_distance(1),
+            config.automation,
+        );
+        let mut endstop_steps = EndstopSteps::from_config(pins);
+        match Calibration::load(&config.calibration_file) {
+            Ok(Some(calibration)) if calibration.problem().is_none() => {
+                println!("Endstops from {}", config.calibration_file);
+                endstop_steps = calibration.steps;
+                monitor.set_calibration(Some(calibration));
+            }
+            Ok(Some(calibration)) => println!(
+                "Ignoring {}: {}",
+                config.calibration_file,
+                calibration.problem().unwrap()
+            ),
+            Ok(None) => {}
+            Err(e) => println!("Cannot read {}: {}", config.calibration_file, e),
+        }
         let t = Door {
-            state: Arc::new(Mutex::new(State::Undefined)),
+            state,
+            monitor,
+            endstop_steps,
             stepper_cancler: lop.get_cancler_clone(),
+            retarget: lop.get_retarget_clone(),
+            halt: lop.get_halt(),
             stepper: lop,
-            door_dog: None,
+            endstops: Endstops {
+                close: hal.input(pins.close_pin, Pull::Up)?,
+                middle: hal.input(pins.middle_pin, Pull::Up)?,
+                furtherest: hal.input(pins.furtherest_pin, Pull::Up)?,
+            },
+            emergency_input: match config.emergency_stop_pin {
+                Some(pin) => Some(hal.input(pin, Pull::Up)?),
+                None => None,
+            },
+            drift_corrections: 0,
+            profiles: MotionProfiles::new(&config),
+            config,
         };
-        let dooro = Arc::new(Mutex::new(t));
-        dooro
+        Ok(Arc::new(Mutex::new(t)))
     }
-    fn calibrate(door_arc: Arc<Mutex<Door>>) {
+    /// Homes on the endstops and leaves the door at the middle switch,
+    /// false when the emergency stop cut it short
+    pub fn calibrate(&mut self) -> bool {
+        self.apply_profile();
         println!("Start door calibration");
-        {
-            let mut door = door_arc.lock().unwrap();
-            let Door {
-                ref mut stepper, ..
-            } = *door;
-            let close = Gpio::new().unwrap().get(25).unwrap().into_input_pullup(); //0
-            let middle = Gpio::new().unwrap().get(23).unwrap().into_input_pullup(); //3209
-            let furtherest = Gpio::new().unwrap().get(24).unwrap().into_input_pullup(); //6960
-
-            let first = 157;
-            let second = 3409;
-            let third = 7722;
+        let first = self.endstop_steps.close;
+        let second = self.endstop_steps.middle;
+        let third = self.endstop_steps.furtherest;
+        let freq = self.stepper.get_plan().calibration_freq;
+        let Door {
+            ref mut stepper,
+            endstops:
+                Endstops {
+                    ref close,
+                    ref middle,
+                    ref furtherest,
+                },
+            ..
+        } = *self;
+        if stepper.is_halted() {
+            return false;
+        }
 
-            //place door in closed position before running
-            if false {
-                for _ in 0..2 {
-                    stepper.turn_while(|| close.is_low(), 1, 150.0);
-                    println!("First: {}", stepper.get_step_count());
-                    stepper.turn_while(|| middle.is_high(), 1, 150.0);
-                    stepper.turn_while(|| middle.is_low(), 1, 150.0);
-                    println!("Second: {}", stepper.get_step_count());
-                    stepper.turn_while(|| furtherest.is_high(), 1, 150.0);
-                    stepper.turn_while(|| furtherest.is_low(), 1, 150.0);
-                    println!("Third: {}", stepper.get_step_count());
-                    stepper.turn_to(0);
-                }
-                return;
-            }
-            stepper.turn_while(
-                || close.is_high() && middle.is_high() && furtherest.is_high(),
-                -1,
-                150.0,
-            );
-            if close.is_low() {
-                stepper.turn_while(|| close.is_low(), 1, 150.0);
-                stepper.set_step_count(first);
-            } else if middle.is_low() {
-                stepper.turn_while(|| middle.is_low(), 1, 150.0);
-                stepper.set_step_count(second);
-            } else if furtherest.is_low() {
-                stepper.turn_while(|| furtherest.is_low(), 1, 150.0);
-                stepper.set_step_count(third);
-            }
-            stepper.turn_to(second - ((second - first) / 2));
-            stepper.turn_while(|| middle.is_high(), 1, 150.0);
-            stepper.turn_while(|| middle.is_low(), 1, 150.0);
+        stepper.turn_while(
+            || close.is_high() && middle.is_high() && furtherest.is_high(),
+            -1,
+            freq,
+        );
+        if close.is_low() {
+            stepper.turn_while(|| close.is_low(), 1, freq);
+            stepper.set_step_count(first);
+        } else if middle.is_low() {
+            stepper.turn_while(|| middle.is_low(), 1, freq);
             stepper.set_step_count(second);
+        } else if furtherest.is_low() {
+            stepper.turn_while(|| furtherest.is_low(), 1, freq);
+            stepper.set_step_count(third);
+        }
+        stepper.turn_to(second - ((second - first) / 2));
+        stepper.turn_while(|| middle.is_high(), 1, freq);
+        stepper.turn_while(|| middle.is_low(), 1, freq);
+        stepper.set_step_count(second);
+        if stepper.is_halted() {
+            println!("Door calibration stopped");
+            return false;
         }
         println!("Finished door calibration");
-        Door::close_door(door_arc.clone());
+        true
+    }
+    /// Homes, passes all endstops `passes` times from the closed position
+    /// and keeps the mean positions for later calibrations. Ends at closed.
+    pub fn measure_endstops(&mut self, passes: u32) -> bool {
+        if !self.calibrate() {
+            return false;
+        }
+        println!("Start endstop measurement, {} passes", passes);
+        let freq = self.stepper.get_plan().calibration_freq;
+        let Door {
+            ref mut stepper,
+            endstops:
+                Endstops {
+                    ref close,
+                    ref middle,
+                    ref furtherest,
+                },
+            ref monitor,
+            ..
+        } = *self;
+        stepper.turn_to(0);
+        let mut readings = Vec::new();
+        for pass in 1..=passes {
+            stepper.turn_while(|| close.is_low(), 1, freq);
+            let close_step = stepper.get_step_count();
+            stepper.turn_while(|| middle.is_high(), 1, freq);
+            stepper.turn_while(|| middle.is_low(), 1, freq);
+            let middle_step = stepper.get_step_count();
+            stepper.turn_while(|| furtherest.is_high(), 1, freq);
+            stepper.turn_while(|| furtherest.is_low(), 1, freq);
+            let furtherest_step = stepper.get_step_count();
+            stepper.turn_to(0);
+            if stepper.is_halted() {
+                println!("Endstop measurement stopped");
+                return false;
+            }
+            let steps = EndstopSteps {
+                close: close_step,
+                middle: middle_step,
+                furtherest: furtherest_step,
+            };
+            monitor.notice(DoorNotice::CalibrationPass {
+                pass,
+                passes,
+                steps,
+            });
+            readings.push(steps);
+        }
+        let calibration = Calibration::from_passes(&readings);
+        if let Some(reason) = calibration.problem() {
+            monitor.notice(DoorNotice::CalibrationFailed { reason });
+            return false;
+        }
+        if let Err(e) = calibration.save(&self.config.calibration_file) {
+            println!("Cannot write {}: {}", self.config.calibration_file, e);
+        }
+        self.endstop_steps = calibration.steps;
+        self.monitor.set_calibration(Some(calibration.clone()));
+        self.monitor
+            .notice(DoorNotice::CalibrationMeasured { calibration });
+        true
+    }
+    /// Takes over a position saved on shutdown instead of homing
+    pub fn restore(&mut self, saved: &SavedPosition) {
+        println!("Door restored {:?} at step {}", saved.state, saved.steps);
+        self.stepper.set_step_count(saved.steps);
+        self.monitor.set_state(saved.state.clone());
+    }
+    /// Switches to a changed motion profile, never during a move
+    fn apply_profile(&mut self) {
+        let Some(motion) = self.profiles.take_pending() else {
+            return;
+        };
+        let plan = MotionPlan::new(&motion, |cm| self.stepper.get_steps(cm));
+        println!("Door motion plan {:?}", plan);
+        self.stepper.set_plan(plan);
+        self.config.stepper.motion = motion;
     }
     pub fn get_cancler(&self) -> Arc<AtomicBool> {
         self.stepper_cancler.clone()
     }
-    pub fn get_state_arc(&self) -> Arc<Mutex<State>> {
-        self.state.clone()
+    pub fn get_retarget(&self) -> Retarget {
+        self.retarget.clone()
     }
-    fn process_event(door_arc: Arc<Mutex<Door>>, event: Event) {
-        use Event::*;
-        use State::*;
-        let state_clone = door_arc.lock().unwrap().get_state_arc();
-        let current_state = state_clone.lock().unwrap().clone();
-        let dl = || door_arc.lock().unwrap();
-        let sl = || state_clone.lock().unwrap();
-
-        match (current_state, event) {
-            (Opened | Opening | Closing, Close) => Door::close_door(door_arc),
-            (Closed | Closing | Opening, Open) => {
-                dl().send_open_signal();
-                Door::open_door(door_arc)
-            }
-
-            //End postions reached
-            (Opening, IsOpen) => {
-                *sl() = Opened;
-                dl().send_open_signal()
-            }
-            (Closing, IsClose) => *sl() = Closed,
-
-            (_, Open) => dl().send_open_signal(),
-
-            (Opened, Hold) => *sl() = Held, // Hold Transitions
-            (Held, Release) => *sl() = Opened,
-
-            (Closed, Lock) => *sl() = Locked, // Lock Transitions
-            (Locked, Unlock) => *sl() = Closed,
-
-            (_, Calibrate) => {
-                *sl() = Undefined;
-                Door::calibrate(door_arc)
-            }
-
-            (_, _) => {} // Total function complete most Events do nothing
-        }
+    pub fn get_halt(&self) -> Halt {
+        self.halt.clone()
     }
-    fn open_door(door_arc: Arc<Mutex<Door>>) {
-        let condi;
-        {
-            let mut door = door_arc.lock().unwrap();
-            let state_clone = door.get_state_arc();
-
-            *state_clone.lock().unwrap() = State::Opening;
-            let open = door.stepper.get_steps(40.0);
-            door.stepper.turn_to(open);
-            condi = door.stepper.get_step_count() == open;
-        }
-        if condi {
-            Door::process_event(door_arc, Event::IsOpen);
-        }
+    pub fn take_emergency_input(&mut self) -> Option<Box<dyn InputPin>> {
+        self.emergency_input.take()
     }
-    fn close_door(door_arc: Arc<Mutex<Door>>) {
-        let condi;
-        {
-            let mut door = door_arc.lock().unwrap();
-            let state_clone = door.get_state_arc();
-
-            *state_clone.lock().unwrap() = State::Closing;
-
-            door.stepper.turn_to(0);
-
-            condi = door.stepper.get_step_count() == 0;
-        }
-        if condi {
-            Door::process_event(door_arc, Event::IsClose);
-        }
+    pub fn get_config(&self) -> &DoorConfig {
+        &self.config
     }
-    fn send_open_signal(&self) {
-        if let Some(dog) = &self.door_dog {
-            let _ = dog.send(());
-        }
+    pub fn open_position(&self) -> i64 {
+        self.stepper.get_steps(self.config.open_distance_cm)
     }
-
-    fn set_watch_dog(&mut self, dog: Sender<()>) {
-        self.door_dog = Some(dog)
+    pub fn max_open_position(&self) -> i64 {
+        self.stepper.get_steps(self.config.max_open_distance_cm)
     }
-}
-pub fn start_door_controller(door_arc: Arc<Mutex<Door>>) -> Sender<Event> {
-    let (tx, rx) = channel::<Event>();
-
-    let (btx, brx) = channel::<()>();
-    let tx_clone = tx.clone();
-    door_arc.lock().unwrap().set_watch_dog(btx);
-
-    spawn(move || {
-        while brx.recv().is_ok() {
-            loop {
-                match brx.recv_timeout(DOOR_COOLDOWN) {
-                    Ok(_) => continue,
-                    Err(_) => {
-                        let _ = tx_clone.send(Event::Close);
-                        break;
-                    }
-                }
-            }
-        }
-    });
-
-    spawn(move || {
-        let mut queue = VecDeque::new();
-        let mut thread: Option<JoinHandle<()>> = None;
-        let state = door_arc.lock().unwrap().get_state_arc();
-        let cancler = door_arc.lock().unwrap().get_cancler();
-        let sl = || state.lock().unwrap();
-        loop {
-            if queue.is_empty() {
-                queue.push_back(rx.recv().unwrap());
-            }
-            while let Ok(event) = rx.try_recv() {
-                queue.push_back(event);
-            }
-            if *sl() == State::Opening {
-                if queue.contains(&Event::Close) {
-                    cancler.store(true, Ordering::SeqCst);
-                    queue.retain(|x| x != &Event::Open);
-                }
-            }
-            if *sl() == State::Closing {
-                if queue.contains(&Event::Open) {
-                    cancler.store(true, Ordering::SeqCst);
-                    queue.retain(|x| x != &Event::Close);
-                }
+    pub fn get_travel(&self) -> u64 {
+        self.stepper.get_travel()
+    }
+    pub fn get_drift_corrections(&self) -> u64 {
+        self.drift_corrections
+    }
+    pub fn add_motor_observer(&mut self, observer: Box<dyn MotorObserver>) {
+        self.stepper.add_observer(observer);
+    }
+    pub fn get_state_arc(&self) -> Arc<Mutex<State>> {
+        self.state.clone()
+    }
+    pub fn get_monitor(&self) -> DoorMonitor {
+        self.monitor.clone()
+    }
+    pub fn get_profiles(&self) -> MotionProfiles {
+        self.profiles.clone()
+    }
+    /// Drives to `target` and re-syncs at the middle endstop on the way.
+    /// The move may be retargeted or halted, so it returns where it stopped.
+    pub fn move_to(&mut self, target: i64) -> i64 {
+        self.apply_profile();
+        let expected = self.endstop_steps.middle;
+        let threshold = self.config.drift_threshold_steps;
+        let Door {
+            ref mut stepper,
+            ref endstops,
+            ref monitor,
+            ref mut drift_corrections,
+            ..
+        } = *self;
+        let sync = SyncPoint {
+            pin: endstops.middle.as_ref(),
+            step: expected,
+        };
+        for deviation in stepper.turn_to_synced(target, sync) {
+            if deviation != 0 {
+                *drift_corrections += 1;
             }
-
-            if thread.is_none() || thread.as_ref().is_some_and(|x| x.is_finished()) {
-                if let Some(event) = queue.pop_front() {
-                    cancler.store(false, Ordering::SeqCst);
-                    println!("Doorstate: {:?} Doorevent: {:?}", *sl(), &event);
-                    let door_arc_clone = door_arc.clone();
-                    thread = Some(spawn(|| {
-                        Door::process_event(door_arc_clone, event);
-                    }));
-                }
+            if deviation.abs() > threshold {
+                monitor.report_fault(DoorFault::Drift {
+                    expected,
+                    measured: expected + deviation,
+                });
             }
         }
-    });
-
-    tx
+        stepper.get_step_count()
+    }
 }
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::door::door::{Event, State};
+
+/// What the controller made of a command, with the state it left the door in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "result", content = "state")]
+pub enum Reply {
+    /// a move towards the state is running
+    Accepted(State),
+    Rejected(State),
+    Completed(State),
+}
+impl Reply {
+    pub fn state(&self) -> &State {
+        match self {
+            Reply::Accepted(s) | Reply::Rejected(s) | Reply::Completed(s) => s,
+        }
+    }
+}
+
+/// Where a move goes, the controller knows the steps of the commanded width
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Closed,
+    Open,
+}
+
+/// Side effects of a transition, run by the controller in this order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// sends the running move elsewhere, comes first as it can fail when
+    /// the move already brakes for its end, the event then waits for it
+    Retarget(Target),
+    /// `Retarget` with a hard stop before turning, for someone in the way
+    Reverse(Target),
+    Move(Target),
+    Calibrate,
+    /// `Calibrate` with endstop measurement passes
+    MeasureEndstops(Option<u32>),
+    ArmCooldown,
+    CancelCooldown,
+    /// holds for `secs` capped at the configured maximum
+    Hold(Option<f32>),
+    Halt,
+    ReleaseHalt,
+    /// answers every deferred command with `Rejected`
+    RejectDeferred,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Context {
+    /// a move or calibration is running
+    pub moving: bool,
+    /// a radar target is in the safety zone
+    pub obstructed: bool,
+    /// an `Open` asks for another width than the commanded one
+    pub new_width: bool,
+    /// the emergency stop button is still held down
+    pub stop_pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub reply: Reply,
+    pub actions: Vec<Action>,
+}
+impl Transition {
+    pub fn to(&self) -> &State {
+        self.reply.state()
+    }
+    pub fn rejected(&self) -> bool {
+        matches!(self.reply, Reply::Rejected(_))
+    }
+}
+
+/// The door state machine without side effects. None defers the event
+/// until the running move ended.
+pub fn transition(state: &State, event: &Event, ctx: Context) -> Option<Transition> {
+    use Event::*;
+    use State::*;
+    let go = |reply: Reply, actions: Vec<Action>| Some(Transition { reply, actions });
+    let rejected = |state: &State| go(Reply::Rejected(state.clone()), vec![]);
+    // a direction change of a running move, or a new move when none runs
+    let redirect = |target: Target| match ctx.moving {
+        true => Action::Retarget(target),
+        false => Action::Move(target),
+    };
+
+    if !state.accepts(event) {
+        return rejected(state);
+    }
+    let t = match (state, event) {
+        (_, Event::EmergencyStop) => go(
+            Reply::Completed(State::EmergencyStop),
+            vec![Action::Halt, Action::CancelCooldown, Action::RejectDeferred],
+        ),
+        (State::EmergencyStop, Reset) if ctx.stop_pressed => rejected(state),
+        (State::EmergencyStop, Reset) if ctx.moving => None,
+        (State::EmergencyStop, Reset) => go(Reply::Completed(Undefined), vec![Action::ReleaseHalt]),
+
+        // someone in the doorway, stop at once and reopen
+        (Closing, Obstruction) => go(
+            Reply::Accepted(Opening),
+            vec![
+                match ctx.moving {
+                    true => Action::Reverse(Target::Open),
+                    false => Action::Move(Target::Open),
+                },
+                Action::ArmCooldown,
+            ],
+        ),
+        // turn the running move around instead of stopping it
+        (Closing, Open { .. }) => go(
+            Reply::Accepted(Opening),
+            vec![redirect(Target::Open), Action::ArmCooldown],
+        ),
+        (Closed, Open { .. }) => go(
+            Reply::Accepted(Opening),
+            vec![Action::Move(Target::Open), Action::ArmCooldown],
+        ),
+        (Opening, Open { .. }) if ctx.new_width => go(
+            Reply::Accepted(Opening),
+            vec![redirect(Target::Open), Action::ArmCooldown],
+        ),
+        (Opening, Open { .. }) => go(Reply::Accepted(Opening), vec![Action::ArmCooldown]),
+        (Opened, Open { .. }) if ctx.new_width => go(
+            Reply::Accepted(Opening),
+            vec![Action::Move(Target::Open), Action::ArmCooldown],
+        ),
+        (state, Open { .. } | Obstruction) => {
+            go(Reply::Completed(state.clone()), vec![Action::ArmCooldown])
+        }
+
+        // nobody gets closed in, the safety zone overrides the radar heuristics
+        (state, Close) if ctx.obstructed => rejected(state),
+        (Opening, Close) => go(
+            Reply::Accepted(Closing),
+            vec![redirect(Target::Closed), Action::CancelCooldown],
+        ),
+        (Opened, Close) => go(
+            Reply::Accepted(Closing),
+            vec![Action::Move(Target::Closed), Action::CancelCooldown],
+        ),
+        (Closing, Close) => go(Reply::Accepted(Closing), vec![]),
+
+        (Opening, Hold { .. }) | (Closing, Lock) if ctx.moving => None,
+        (Opened | Held, Hold { secs }) => go(
+            Reply::Completed(Held),
+            vec![Action::CancelCooldown, Action::Hold(*secs)],
+        ),
+        (Held, Release) => go(Reply::Completed(Opened), vec![Action::ArmCooldown]),
+        (Closed, Lock) => go(Reply::Completed(Locked), vec![]),
+        (Locked, Unlock) => go(Reply::Completed(Closed), vec![]),
+
+        (_, MeasureEndstops { passes }) => go(
+            Reply::Accepted(Undefined),
+            vec![Action::CancelCooldown, Action::MeasureEndstops(*passes)],
+        ),
+        (_, Calibrate) => go(
+            Reply::Accepted(Undefined),
+            vec![Action::CancelCooldown, Action::Calibrate],
+        ),
+
+        // ends of moves, sent by the controller itself
+        (Opening, IsOpen) => go(Reply::Completed(Opened), vec![Action::ArmCooldown]),
+        (Closing, IsClose) => go(Reply::Completed(Closed), vec![]),
+        (Undefined, Calibrated) => go(Reply::Accepted(Closing), vec![Action::Move(Target::Closed)]),
+
+        (state, _) => rejected(state),
+    };
+    // one job at a time
+    let starts_job = |t: &Transition| {
+        t.actions.iter().any(|a| {
+            matches!(
+                a,
+                Action::Move(_) | Action::Calibrate | Action::MeasureEndstops(_)
+            )
+        })
+    };
+    match t {
+        Some(t) if ctx.moving && starts_job(&t) => None,
+        t => t,
+    }
+}
+
+pub const STATES: [State; 8] = [
+    State::Undefined,
+    State::Closed,
+    State::Opening,
+    State::Opened,
+    State::Held,
+    State::Closing,
+    State::Locked,
+    State::EmergencyStop,
+];
+
+/// One event of every kind, payloads left empty
+pub fn events() -> [Event; 14] {
+    [
+        Event::Open { width_cm: None },
+        Event::Close,
+        Event::Hold { secs: None },
+        Event::Release,
+        Event::IsOpen,
+        Event::IsClose,
+        Event::Lock,
+        Event::Unlock,
+        Event::Calibrate,
+        Event::MeasureEndstops { passes: None },
+        Event::Calibrated,
+        Event::Obstruction,
+        Event::EmergencyStop,
+        Event::Reset,
+    ]
+}
+
+pub fn contexts() -> Vec<Context> {
+    (0..16)
+        .map(|bits| Context {
+            moving: bits & 1 != 0,
+            obstructed: bits & 2 != 0,
+            new_width: bits & 4 != 0,
+            stop_pressed: bits & 8 != 0,
+        })
+        .collect()
+}
+
+/// Every state, event and context with what the machine does about it
+pub fn table() -> Vec<(State, Event, Context, Option<Transition>)> {
+    let mut rows = Vec::new();
+    for state in STATES {
+        for event in events() {
+            for ctx in contexts() {
+                let t = transition(&state, &event, ctx);
+                rows.push((state.clone(), event.clone(), ctx, t));
+            }
+        }
+    }
+    rows
+}
+
+/// State changes of `table`, labelled with the events causing them
+fn edges() -> BTreeMap<(String, String), Vec<&'static str>> {
+    let mut edges: BTreeMap<(String, String), Vec<&'static str>> = BTreeMap::new();
+    for (state, event, _, t) in table() {
+        let Some(t) = t.filter(|t| t.to() != &state) else {
+            continue;
+        };
+        let labels = edges
+            .entry((format!("{:?}", state), format!("{:?}", t.to())))
+            .or_default();
+        if !labels.contains(&label(&event)) {
+            labels.push(label(&event));
+        }
+    }
+    edges
+}
+
+fn label(event: &Event) -> &'static str {
+    match event {
+        Event::Open { .. } => "Open",
+        Event::Close => "Close",
+        Event::Hold { .. } => "Hold",
+        Event::Release => "Release",
+        Event::IsOpen => "IsOpen",
+        Event::IsClose => "IsClose",
+        Event::Lock => "Lock",
+        Event::Unlock => "Unlock",
+        Event::Calibrate => "Calibrate",
+        Event::MeasureEndstops { .. } => "MeasureEndstops",
+        Event::Calibrated => "Calibrated",
+        Event::Obstruction => "Obstruction",
+        Event::EmergencyStop => "EmergencyStop",
+        Event::Reset => "Reset",
+    }
+}
+
+pub fn mermaid() -> String {
+    let mut out = String::from("stateDiagram-v2\n    [*] --> Undefined\n");
+    for ((from, to), labels) in edges() {
+        out += &format!("    {} --> {}: {}\n", from, to, labels.join(", "));
+    }
+    out
+}
+
+pub fn graphviz() -> String {
+    let mut out =
+        String::from("digraph door {\n    start [shape=point];\n    start -> Undefined;\n");
+    for ((from, to), labels) in edges() {
+        out += &format!(
+            "    {} -> {} [label=\"{}\"];\n",
+            from,
+            to,
+            labels.join("\\n")
+        );
+    }
+    out += "}\n";
+    out
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: state-machine <mermaid|dot>";
+    match args.first().map(String::as_str) {
+        Some("mermaid") => print!("{}", mermaid()),
+        Some("dot") => print!("{}", graphviz()),
+        _ => return Err(usage.into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rules every row of `table` has to follow, returns the broken ones
+    fn check() -> Vec<String> {
+        let mut problems = Vec::new();
+        for (state, event, ctx, t) in table() {
+            let row = format!("{:?} + {:?} with {:?}", state, event, ctx);
+            let Some(t) = t else {
+                if !ctx.moving {
+                    problems.push(format!("{}: deferred without a running move", row));
+                }
+                continue;
+            };
+            if !state.accepts(&event) && !t.rejected() {
+                problems.push(format!(
+                    "{}: acted on an event the state does not accept",
+                    row
+                ));
+            }
+            if t.rejected() && (t.to() != &state || !t.actions.is_empty()) {
+                problems.push(format!("{}: rejected but changed something", row));
+            }
+            for action in &t.actions {
+                match action {
+                    Action::Move(_) | Action::Calibrate | Action::MeasureEndstops(_)
+                        if ctx.moving =>
+                    {
+                        problems.push(format!("{}: second job while moving", row))
+                    }
+                    Action::Retarget(_) | Action::Reverse(_) if !ctx.moving => {
+                        problems.push(format!("{}: retarget without a move", row))
+                    }
+                    Action::Move(Target::Closed) | Action::Retarget(Target::Closed)
+                        if ctx.obstructed && event == Event::Close =>
+                    {
+                        problems.push(format!("{}: closes on an obstruction", row))
+                    }
+                    _ => {}
+                }
+            }
+            if state == State::Closing
+                && event == Event::Obstruction
+                && ctx.moving
+                && !t.actions.contains(&Action::Reverse(Target::Open))
+            {
+                problems.push(format!("{}: keeps closing without a hard stop", row));
+            }
+            match event {
+                Event::EmergencyStop if !t.actions.contains(&Action::Halt) => {
+                    problems.push(format!("{}: emergency stop does not halt", row))
+                }
+                Event::Reset if ctx.stop_pressed && !t.rejected() => {
+                    problems.push(format!("{}: reset while the stop button is held", row))
+                }
+                Event::EmergencyStop | Event::Reset => {}
+                _ if state == State::EmergencyStop && !t.rejected() => {
+                    problems.push(format!("{}: left the emergency stop without reset", row))
+                }
+                _ => {}
+            }
+            if matches!(t.to(), State::Opening | State::Closing)
+                && !matches!(state, State::Opening | State::Closing)
+                && !t.actions.iter().any(|a| matches!(a, Action::Move(_)))
+            {
+                problems.push(format!("{}: starts moving without a move", row));
+            }
+        }
+        problems
+    }
+
+    #[test]
+    fn every_transition_follows_the_rules() {
+        let problems = check();
+        assert!(problems.is_empty(), "{}", problems.join("\n"));
+    }
+
+    #[test]
+    fn statemachine_md_is_current() {
+        let doc = include_str!("../../statemachine.md");
+        let block = doc
+            .split("```mermaid\n")
+            .nth(1)
+            .and_then(|rest| rest.split("```").next())
+            .expect("statemachine.md has a mermaid block");
+        assert_eq!(block, mermaid(), "regenerate with `state-machine mermaid`");
+    }
+}
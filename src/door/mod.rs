@@ -1,6 +1,17 @@
 #![allow(dead_code)]
+pub mod audit;
+pub mod calibration;
+pub mod controller;
 pub mod detector;
+#[allow(clippy::module_inception)]
 pub mod door;
+pub mod machine;
+pub mod monitor;
+pub mod motion;
 pub mod motor;
+pub mod position;
+pub mod profile;
+pub mod replay;
 pub mod routes;
+pub mod stats;
 pub mod stepper;
@@ -0,0 +1,208 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::door::{
+    calibration::{Calibration, EndstopSteps},
+    door::{AutomationMode, Event, State},
+    motor::MotorObserver,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoorStatus {
+    pub state: State,
+    pub steps: i64,
+    pub position_cm: f32,
+    pub locked: bool,
+    pub last_event: Option<Event>,
+    /// seconds until a hold is released
+    pub hold_remaining_secs: Option<f32>,
+    /// commanded width while the door opens or is open
+    pub open_width_cm: Option<f32>,
+    pub automation: AutomationMode,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum DoorFault {
+    /// the middle endstop was passed `measured - expected` steps off
+    Drift { expected: i64, measured: i64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum DoorNotice {
+    /// the door is released and closes after `remaining_secs`
+    HoldExpiring {
+        remaining_secs: f32,
+    },
+    /// progress of `MeasureEndstops`
+    CalibrationPass {
+        pass: u32,
+        passes: u32,
+        steps: EndstopSteps,
+    },
+    CalibrationMeasured {
+        calibration: Calibration,
+    },
+    CalibrationFailed {
+        reason: String,
+    },
+}
+
+type Listener = Box<dyn Fn(&DoorStatus) + Send>;
+type FaultListener = Box<dyn Fn(&DoorFault) + Send>;
+type NoticeListener = Box<dyn Fn(&DoorNotice) + Send>;
+
+/// Read side of a `Door` that works without the door lock,
+/// which is held for the whole duration of a move
+#[derive(Clone)]
+pub struct DoorMonitor {
+    state: Arc<Mutex<State>>,
+    steps: Arc<AtomicI64>,
+    cm_per_step: f32,
+    last_event: Arc<Mutex<Option<Event>>>,
+    hold_until: Arc<Mutex<Option<Instant>>>,
+    open_target: Arc<Mutex<Option<i64>>>,
+    calibration: Arc<Mutex<Option<Calibration>>>,
+    automation: Arc<Mutex<AutomationMode>>,
+    listeners: Arc<Mutex<Vec<Listener>>>,
+    fault_listeners: Arc<Mutex<Vec<FaultListener>>>,
+    notice_listeners: Arc<Mutex<Vec<NoticeListener>>>,
+}
+impl DoorMonitor {
+    pub fn new(
+        state: Arc<Mutex<State>>,
+        steps: Arc<AtomicI64>,
+        cm_per_step: f32,
+        automation: AutomationMode,
+    ) -> Self {
+        Self {
+            state,
+            steps,
+            cm_per_step,
+            last_event: Arc::new(Mutex::new(None)),
+            hold_until: Arc::new(Mutex::new(None)),
+            open_target: Arc::new(Mutex::new(None)),
+            calibration: Arc::new(Mutex::new(None)),
+            automation: Arc::new(Mutex::new(automation)),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            fault_listeners: Arc::new(Mutex::new(Vec::new())),
+            notice_listeners: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    pub fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+    pub fn cm_per_step(&self) -> f32 {
+        self.cm_per_step
+    }
+    pub fn status(&self) -> DoorStatus {
+        let steps = self.steps.load(Ordering::SeqCst);
+        let state = self.state();
+        DoorStatus {
+            locked: state == State::Locked,
+            state,
+            steps,
+            position_cm: steps as f32 * self.cm_per_step,
+            last_event: self.last_event.lock().unwrap().clone(),
+            hold_remaining_secs: self
+                .hold_until
+                .lock()
+                .unwrap()
+                .map(|t| t.saturating_duration_since(Instant::now()).as_secs_f32()),
+            open_width_cm: self
+                .open_target
+                .lock()
+                .unwrap()
+                .map(|steps| steps as f32 * self.cm_per_step),
+            automation: self.automation(),
+        }
+    }
+    /// listeners are called on every state change
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn(&DoorStatus) + Send + 'static,
+    {
+        self.listeners.lock().unwrap().push(Box::new(listener));
+    }
+    pub fn subscribe_faults<F>(&self, listener: F)
+    where
+        F: Fn(&DoorFault) + Send + 'static,
+    {
+        self.fault_listeners
+            .lock()
+            .unwrap()
+            .push(Box::new(listener));
+    }
+    pub fn report_fault(&self, fault: DoorFault) {
+        println!("Door fault: {:?}", fault);
+        for l in self.fault_listeners.lock().unwrap().iter() {
+            l(&fault);
+        }
+    }
+    pub fn subscribe_notices<F>(&self, listener: F)
+    where
+        F: Fn(&DoorNotice) + Send + 'static,
+    {
+        self.notice_listeners
+            .lock()
+            .unwrap()
+            .push(Box::new(listener));
+    }
+    pub fn notice(&self, notice: DoorNotice) {
+        println!("Door notice: {:?}", notice);
+        for l in self.notice_listeners.lock().unwrap().iter() {
+            l(&notice);
+        }
+    }
+    /// shown in the status until the next state change
+    pub fn set_hold(&self, until: Option<Instant>) {
+        *self.hold_until.lock().unwrap() = until;
+    }
+    /// step position of the commanded width
+    pub fn set_open_target(&self, target: Option<i64>) {
+        *self.open_target.lock().unwrap() = target;
+    }
+    /// the last endstop measurement, None while the config is used
+    pub fn calibration(&self) -> Option<Calibration> {
+        self.calibration.lock().unwrap().clone()
+    }
+    pub fn set_calibration(&self, calibration: Option<Calibration>) {
+        *self.calibration.lock().unwrap() = calibration;
+    }
+    pub fn automation(&self) -> AutomationMode {
+        *self.automation.lock().unwrap()
+    }
+    pub fn set_automation(&self, mode: AutomationMode) {
+        println!("Door automation {:?}", mode);
+        *self.automation.lock().unwrap() = mode;
+        self.notify();
+    }
+    pub fn record_event(&self, event: Event) {
+        *self.last_event.lock().unwrap() = Some(event);
+    }
+    pub fn set_state(&self, state: State) {
+        *self.state.lock().unwrap() = state;
+        self.notify();
+    }
+    pub fn notify(&self) {
+        let status = self.status();
+        for l in self.listeners.lock().unwrap().iter() {
+            l(&status);
+        }
+    }
+}
+
+/// Sends the status with the position to the listeners while the door moves
+impl MotorObserver for DoorMonitor {
+    fn on_step(&mut self, _step: i64) {
+        self.notify();
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use crate::config::MotionConfig;
+
+/// fastest pulse train the stepper driver follows, steps/s
+pub const MAX_PULSE_FREQ: f32 = 25_000.0;
+/// longest acceleration phase, a ramp holds one entry per step of it
+pub const MAX_RAMP_SECS: f32 = 10.0;
+/// ramp levels a hard stop drops per pulse, it brakes this many times
+/// harder than the configured acceleration
+pub const HARD_STOP_LEVELS: usize = 10;
+
+/// Time to accelerate over `span`, in the unit of `span` and `acceleration`
+pub fn ramp_secs(span: f32, acceleration: f32, s_curve: bool) -> f32 {
+    // smoothstep peaks at 1.5 times its mean slope
+    match s_curve {
+        true => 1.5 * span / acceleration,
+        false => span / acceleration,
+    }
+}
+
+/// Ramp level of the next pulse of a move that ran its last one at `level`,
+/// with `ahead` steps left to the target in its direction. It speeds up
+/// while there is room to brake in front of the target and brakes
+/// otherwise, `stop` brakes wherever it is and `hard` brakes at
+/// `HARD_STOP_LEVELS`. None once it stands, the move then starts over
+/// towards the target if it is not there.
+pub fn next_level(
+    level: usize,
+    max_level: usize,
+    ahead: i64,
+    stop: bool,
+    hard: bool,
+) -> Option<usize> {
+    if !stop && ahead >= 1 && ahead >= level as i64 {
+        Some((level + 1).min(max_level).min(ahead as usize - 1))
+    } else if level == 0 {
+        None
+    } else if hard {
+        Some(level.saturating_sub(HARD_STOP_LEVELS))
+    } else {
+        Some(level - 1)
+    }
+}
+
+/// Motion limits in steps, built from the cm based `MotionConfig`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionPlan {
+    pub start_freq: f32,
+    pub max_freq: f32,
+    /// steps/s²
+    pub acceleration: f32,
+    pub s_curve: bool,
+    /// steps/s of homing and endstop measurement
+    pub calibration_freq: f32,
+}
+impl MotionPlan {
+    pub fn new<F>(config: &MotionConfig, get_steps: F) -> Self
+    where
+        F: Fn(f32) -> i64,
+    {
+        let start_freq = get_steps(config.start_speed_cm_s).max(1) as f32;
+        Self {
+            start_freq,
+            max_freq: (get_steps(config.max_speed_cm_s) as f32).max(start_freq),
+            acceleration: get_steps(config.acceleration_cm_s2).max(1) as f32,
+            s_curve: config.s_curve,
+            calibration_freq: get_steps(config.calibration_speed_cm_s).max(1) as f32,
+        }
+    }
+
+    /// Pulse periods from standstill up to cruise speed, one entry per step.
+    /// Speed follows time, not steps, so the acceleration is constant
+    /// (trapezoid) or rises and falls smoothly (S-curve). The last entry is
+    /// the cruise period. A ramp longer than `MAX_RAMP_SECS` is cut to it
+    /// with a steeper acceleration.
+    pub fn ramp(&self) -> Vec<Duration> {
+        let span = self.max_freq - self.start_freq;
+        let ramp_time = ramp_secs(span, self.acceleration, self.s_curve).min(MAX_RAMP_SECS);
+        let mut periods = Vec::new();
+        let mut t = 0.0;
+        loop {
+            let x = match ramp_time > 0.0 {
+                true => (t / ramp_time).min(1.0),
+                false => 1.0,
+            };
+            let shape = match self.s_curve {
+                true => x * x * (3.0 - 2.0 * x),
+                false => x,
+            };
+            let freq = self.start_freq + span * shape;
+            periods.push(Duration::from_secs_f32(1.0 / freq));
+            if x >= 1.0 {
+                return periods;
+            }
+            t += 1.0 / freq;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// steps of the default 1600 pulse motor on an 8 cm wheel
+    fn plan(config: &MotionConfig) -> MotionPlan {
+        MotionPlan::new(config, |cm| (cm / 8.0 * 1600.0) as i64)
+    }
+
+    fn freqs(ramp: &[Duration]) -> Vec<f32> {
+        ramp.iter().map(|p| 1.0 / p.as_secs_f32()).collect()
+    }
+
+    /// ramp levels of the pulses of a move over `steps` from standstill,
+    /// with the steps left in front of each
+    fn schedule(max_level: usize, steps: i64) -> Vec<(usize, i64)> {
+        let next = |level, ahead| next_level(level, max_level, ahead, false, false);
+        let mut pulses = vec![(0, steps)];
+        let mut ahead = steps - 1;
+        while let Some(level) = next(pulses[pulses.len() - 1].0, ahead) {
+            pulses.push((level, ahead));
+            ahead -= 1;
+        }
+        pulses
+    }
+
+    #[test]
+    fn trapezoid_at_the_defaults() {
+        let plan = plan(&MotionConfig::default());
+        assert_eq!((plan.start_freq, plan.max_freq), (300.0, 25_000.0));
+        let ramp = plan.ramp();
+        // (300 + 25000) / 2 steps/s over 24700 / 108000 s
+        assert!((2850..2950).contains(&ramp.len()), "{} steps", ramp.len());
+        let secs: f32 = ramp.iter().map(Duration::as_secs_f32).sum();
+        assert!((secs - 0.229).abs() < 0.005, "{} s", secs);
+    }
+
+    #[test]
+    fn periods_never_grow() {
+        for s_curve in [false, true] {
+            let ramp = plan(&MotionConfig {
+                s_curve,
+                ..Default::default()
+            })
+            .ramp();
+            assert!(ramp.windows(2).all(|w| w[0] >= w[1]), "s_curve {}", s_curve);
+            assert_eq!(ramp.last(), Some(&Duration::from_secs_f32(1.0 / 25_000.0)));
+        }
+    }
+
+    #[test]
+    fn s_curve_peaks_at_the_configured_acceleration() {
+        let plan = plan(&MotionConfig {
+            s_curve: true,
+            ..Default::default()
+        });
+        let ramp = plan.ramp();
+        let f = freqs(&ramp);
+        // over a few steps, single periods are rounded to whole nanoseconds
+        let peak = (0..ramp.len() - 10)
+            .map(|i| {
+                let dt: f32 = ramp[i..i + 10].iter().map(Duration::as_secs_f32).sum();
+                (f[i + 10] - f[i]) / dt
+            })
+            .fold(0.0, f32::max);
+        assert!(
+            (peak / plan.acceleration - 1.0).abs() < 0.03,
+            "peak {} configured {}",
+            peak,
+            plan.acceleration
+        );
+    }
+
+    #[test]
+    fn long_ramps_are_cut() {
+        let plan = MotionPlan {
+            start_freq: 300.0,
+            max_freq: 25_000.0,
+            acceleration: 1.0,
+            s_curve: false,
+            calibration_freq: 150.0,
+        };
+        let ramp = plan.ramp();
+        let secs: f32 = ramp.iter().map(Duration::as_secs_f32).sum();
+        assert!(secs <= MAX_RAMP_SECS * 1.01, "{} s", secs);
+        assert_eq!(ramp.last(), Some(&Duration::from_secs_f32(1.0 / 25_000.0)));
+    }
+
+    #[test]
+    fn full_move_accelerates_cruises_and_brakes() {
+        let ramp = plan(&MotionConfig::default()).ramp();
+        let max_level = ramp.len() - 1;
+        let pulses = schedule(max_level, 20_000);
+        assert_eq!(pulses.len(), 20_000);
+
+        let levels: Vec<usize> = pulses.iter().map(|(level, _)| *level).collect();
+        let top = levels.iter().position(|l| *l == max_level).unwrap();
+        let brake = levels.iter().rposition(|l| *l == max_level).unwrap();
+        assert_eq!(top, max_level);
+        assert!(levels[..=top].windows(2).all(|w| w[1] == w[0] + 1));
+        assert!(brake - top > 10_000, "cruised {} steps", brake - top);
+        assert!(levels[brake..].windows(2).all(|w| w[1] + 1 == w[0]));
+        // the last pulse is at start speed, like the first
+        assert_eq!(ramp[*levels.last().unwrap()], ramp[0]);
+    }
+
+    #[test]
+    fn short_moves_stay_below_their_braking_distance() {
+        for steps in [1, 2, 3, 10, 501, 2000] {
+            let pulses = schedule(2900, steps);
+            assert_eq!(pulses.len() as i64, steps);
+            assert!(
+                pulses.iter().all(|(level, ahead)| (*level as i64) < *ahead),
+                "{} steps",
+                steps
+            );
+            assert_eq!(pulses.last().unwrap().0, 0);
+        }
+    }
+
+    #[test]
+    fn stop_brakes_wherever_it_is() {
+        let mut level = 2900;
+        let mut pulses = 0;
+        while let Some(next) = next_level(level, 2900, 100_000, true, false) {
+            level = next;
+            pulses += 1;
+        }
+        assert_eq!(pulses, 2900);
+    }
+
+    #[test]
+    fn hard_stop_turns_around_within_a_tenth() {
+        let mut level = 2900;
+        let mut pulses = 0;
+        // the target lies behind the carriage
+        while let Some(next) = next_level(level, 2900, -5000, false, true) {
+            level = next;
+            pulses += 1;
+        }
+        assert_eq!(pulses, 290);
+        // a hard stop still speeds up towards a target in front
+        assert_eq!(next_level(100, 2900, 5000, false, true), Some(101));
+    }
+}
@@ -1,3 +1,4 @@
+/// Follows the door position while the stepper runs, see `Stepper::add_observer`
 pub trait MotorObserver: Send {
     fn on_step(&mut self, step: i64);
 }
@@ -0,0 +1,121 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::door::{door::State, monitor::DoorStatus};
+
+/// Where the door stood at a clean shutdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPosition {
+    pub state: State,
+    pub steps: i64,
+    /// ms since epoch
+    pub saved_at: u64,
+}
+impl SavedPosition {
+    /// None while the door moves or its position is unknown
+    pub fn from_status(status: &DoorStatus) -> Option<Self> {
+        let state = match status.state {
+            State::Closed | State::Locked | State::Opened => status.state.clone(),
+            // nobody renews the hold after a restart
+            State::Held => State::Opened,
+            _ => return None,
+        };
+        Some(Self {
+            state,
+            steps: status.steps,
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_millis() as u64,
+        })
+    }
+
+    /// written aside and renamed, the door skips homing on what it reads
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self).unwrap())?;
+        fs::rename(tmp, path)
+    }
+
+    /// Reads and removes the file, it only holds until the door moves again
+    pub fn take<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        fs::remove_file(&path)?;
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::door::door::AutomationMode;
+
+    fn status(state: State) -> DoorStatus {
+        DoorStatus {
+            state,
+            steps: 1234,
+            position_cm: 6.17,
+            locked: false,
+            last_event: None,
+            hold_remaining_secs: None,
+            open_width_cm: None,
+            automation: AutomationMode::Auto,
+        }
+    }
+
+    #[test]
+    fn only_resting_doors_are_saved() {
+        for (state, saved) in [
+            (State::Closed, Some(State::Closed)),
+            (State::Locked, Some(State::Locked)),
+            (State::Opened, Some(State::Opened)),
+            (State::Held, Some(State::Opened)),
+            (State::Opening, None),
+            (State::Closing, None),
+            (State::Undefined, None),
+            (State::EmergencyStop, None),
+        ] {
+            let position = SavedPosition::from_status(&status(state.clone()));
+            assert_eq!(
+                position.as_ref().map(|p| p.state.clone()),
+                saved,
+                "{:?}",
+                state
+            );
+            assert!(position.is_none_or(|p| p.steps == 1234));
+        }
+    }
+
+    #[test]
+    fn take_reads_once() {
+        let dir = std::env::temp_dir().join(format!("door-position-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("door_position.json");
+
+        let saved = SavedPosition::from_status(&status(State::Opened)).unwrap();
+        saved.save(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        let taken = SavedPosition::take(&path).unwrap().unwrap();
+        assert_eq!((taken.state, taken.steps), (State::Opened, 1234));
+        assert!(SavedPosition::take(&path).unwrap().is_none());
+
+        // a broken file is an error and gone afterwards, the door homes
+        fs::write(&path, "{\"state\": \"Opened\"").unwrap();
+        let err = SavedPosition::take(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
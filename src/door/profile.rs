@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use chrono::{Local, Timelike};
+use serde::Serialize;
+
+use crate::config::{DoorConfig, MotionConfig, ProfileSwitch};
+
+/// name of `door.stepper.motion` among the profiles
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The motion profile of the door and what it can be switched to
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileStatus {
+    /// None after an edit that matches no named profile
+    pub name: Option<String>,
+    pub motion: MotionConfig,
+    pub profiles: BTreeMap<String, MotionConfig>,
+    /// not picked up by the door yet, it applies from the next move on
+    pub pending: bool,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Unknown(String),
+    Invalid(Vec<String>),
+}
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Unknown(name) => write!(f, "no motion profile named {}", name),
+            ProfileError::Invalid(problems) => write!(f, "{}", problems.join(", ")),
+        }
+    }
+}
+impl std::error::Error for ProfileError {}
+
+type Listener = Box<dyn Fn(&ProfileStatus) + Send>;
+
+struct Inner {
+    steps_per_cm: f32,
+    name: Option<String>,
+    motion: MotionConfig,
+    profiles: BTreeMap<String, MotionConfig>,
+    /// set until the door picked the profile up
+    pending: bool,
+    listeners: Vec<Listener>,
+}
+impl Inner {
+    fn status(&self) -> ProfileStatus {
+        ProfileStatus {
+            name: self.name.clone(),
+            motion: self.motion.clone(),
+            profiles: self.profiles.clone(),
+            pending: self.pending,
+        }
+    }
+    fn changed(&mut self, name: Option<String>, motion: MotionConfig) -> ProfileStatus {
+        println!(
+            "Door motion profile {}",
+            name.as_deref().unwrap_or("custom")
+        );
+        self.name = name;
+        self.motion = motion;
+        self.pending = true;
+        let status = self.status();
+        for l in self.listeners.iter() {
+            l(&status);
+        }
+        status
+    }
+}
+
+/// Speed limits the door moves with, changed between moves only
+#[derive(Clone)]
+pub struct MotionProfiles(Arc<Mutex<Inner>>);
+impl MotionProfiles {
+    pub fn new(config: &DoorConfig) -> Self {
+        let motion = config.stepper.motion.clone();
+        let mut profiles = config.profiles.clone();
+        profiles.insert(DEFAULT_PROFILE.to_string(), motion.clone());
+        Self(Arc::new(Mutex::new(Inner {
+            steps_per_cm: config.stepper.steps_per_cm(),
+            name: Some(DEFAULT_PROFILE.to_string()),
+            motion,
+            profiles,
+            pending: false,
+            listeners: Vec::new(),
+        })))
+    }
+
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn(&ProfileStatus) + Send + 'static,
+    {
+        self.0.lock().unwrap().listeners.push(Box::new(listener));
+    }
+
+    pub fn status(&self) -> ProfileStatus {
+        self.0.lock().unwrap().status()
+    }
+
+    pub fn switch(&self, name: &str) -> Result<ProfileStatus, ProfileError> {
+        let mut inner = self.0.lock().unwrap();
+        let Some(motion) = inner.profiles.get(name).cloned() else {
+            return Err(ProfileError::Unknown(name.to_string()));
+        };
+        Ok(inner.changed(Some(name.to_string()), motion))
+    }
+
+    /// Takes over edited limits, named after the profile they match if any
+    pub fn set(&self, motion: MotionConfig) -> Result<ProfileStatus, ProfileError> {
+        let mut inner = self.0.lock().unwrap();
+        let problems = motion.problems(inner.steps_per_cm);
+        if !problems.is_empty() {
+            return Err(ProfileError::Invalid(problems));
+        }
+        let name = inner
+            .profiles
+            .iter()
+            .find(|(_, m)| **m == motion)
+            .map(|(name, _)| name.clone());
+        Ok(inner.changed(name, motion))
+    }
+
+    /// The profile changed since the last call, for the door before a move
+    pub fn take_pending(&self) -> Option<MotionConfig> {
+        let mut inner = self.0.lock().unwrap();
+        if !inner.pending {
+            return None;
+        }
+        inner.pending = false;
+        let status = inner.status();
+        for l in inner.listeners.iter() {
+            l(&status);
+        }
+        Some(inner.motion.clone())
+    }
+
+    /// Switches at the times of `schedule` every day. A manual switch holds
+    /// until the next scheduled one, at start the one due last applies.
+    pub fn start_schedule(&self, schedule: Vec<ProfileSwitch>) {
+        let mut schedule: Vec<(u32, String)> = schedule
+            .into_iter()
+            .filter_map(|s| Some((s.minute()?, s.profile)))
+            .collect();
+        if schedule.is_empty() {
+            return;
+        }
+        schedule.sort();
+        let profiles = self.clone();
+        thread::spawn(move || {
+            let mut applied = None;
+            loop {
+                let now = Local::now();
+                let due = due_switch(&schedule, now.hour() * 60 + now.minute());
+                if applied != Some(due) {
+                    let (_, name) = &schedule[due];
+                    if let Err(e) = profiles.switch(name) {
+                        println!("Scheduled profile switch failed: {}", e);
+                    }
+                    applied = Some(due);
+                }
+                thread::sleep(Duration::from_secs(20));
+            }
+        });
+    }
+}
+
+/// Index of the switch in force at `minute`, `schedule` sorted by minute.
+/// Before the first switch of the day the last one of yesterday holds.
+fn due_switch(schedule: &[(u32, String)], minute: u32) -> usize {
+    schedule
+        .iter()
+        .rposition(|(at, _)| *at <= minute)
+        .unwrap_or(schedule.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_due_switch_wins() {
+        let schedule = vec![(6 * 60, "day".to_string()), (22 * 60, "night".to_string())];
+        for (minute, due) in [
+            (0, 1),
+            (5 * 60 + 59, 1),
+            (6 * 60, 0),
+            (12 * 60, 0),
+            (22 * 60, 1),
+            (23 * 60 + 59, 1),
+        ] {
+            assert_eq!(due_switch(&schedule, minute), due, "minute {}", minute);
+        }
+        let single = vec![(8 * 60, "quiet".to_string())];
+        assert_eq!(due_switch(&single, 0), 0);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    thread::{JoinHandle, sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Thresholds,
+    door::detector::{RadarRecord, Target},
+};
+
+pub fn read_records<P: AsRef<Path>>(path: P) -> Result<Vec<RadarRecord>, csv::Error> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let mut skipped = 0;
+    let mut records: Vec<RadarRecord> = rdr
+        .deserialize()
+        .filter_map(|r| r.map_err(|_| skipped += 1).ok())
+        .collect();
+    if skipped > 0 {
+        println!("Replay: skipped {} unreadable rows", skipped);
+    }
+    // both detector threads append to the same file
+    records.sort_by_key(|r| r.timestamp);
+    Ok(records)
+}
+
+/// Feeds recorded frames through `Target::update_at` in their original timing,
+/// `speed` > 1.0 plays faster and has to be positive. Every open/close
+/// decision is printed, the callback gets the targets of each frame like a
+/// `Detector` callback.
+pub fn start<P, F>(
+    path: P,
+    speed: f32,
+    thresholds: Thresholds,
+    mut callback: F,
+) -> Result<JoinHandle<()>, csv::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(u8, [Target; 3]) + Send + 'static,
+{
+    let records = read_records(path)?;
+    println!("Replaying {} radar frames", records.len());
+
+    Ok(spawn(move || {
+        let Some(first) = records.first().map(|r| r.timestamp) else {
+            return;
+        };
+        let start = Instant::now();
+        let mut targets: HashMap<u8, [Target; 3]> = HashMap::new();
+        let (mut opens, mut closes) = (0, 0);
+
+        for record in &records {
+            let offset = Duration::from_millis(record.timestamp - first).div_f32(speed);
+            if let Some(wait) = offset.checked_sub(start.elapsed()) {
+                sleep(wait);
+            }
+
+            let arr = targets.entry(record.uart).or_insert_with(|| {
+                [
+                    Target::new(thresholds),
+                    Target::new(thresholds),
+                    Target::new(thresholds),
+                ]
+            });
+            for (i, (target, (x, y, s, res))) in arr.iter_mut().zip(record.raw()).enumerate() {
+                target.update_at(x, y, s, res, record.timestamp as u128);
+                if !target.is_alive() {
+                    continue;
+                }
+                let decision = if target.is_door_open() {
+                    opens += 1;
+                    "open"
+                } else if target.is_close_door() {
+                    closes += 1;
+                    "close"
+                } else {
+                    continue;
+                };
+                println!(
+                    "Replay +{:.1}s uart {} target {} at {:?}: {}",
+                    (record.timestamp - first) as f32 / 1000.0,
+                    record.uart,
+                    i,
+                    (x, y),
+                    decision
+                );
+            }
+            callback(record.uart, arr.clone());
+        }
+        println!(
+            "Replay finished: {} open, {} close decisions",
+            opens, closes
+        );
+    }))
+}
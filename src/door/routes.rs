@@ -1,30 +1,321 @@
 use std::sync::Arc;
 
-use axum::{Router, extract::State, routing::get};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
 
-use crate::{door, state::AppState};
+use crate::{
+    config::MotionConfig,
+    door::{
+        audit::{AuditEntry, AuditQuery, Source},
+        calibration::Calibration,
+        door::{self, AutomationMode, Event},
+        machine::Reply,
+        monitor::DoorStatus,
+        profile::{ProfileError, ProfileStatus},
+        stats::DoorStats,
+    },
+    state::AppState,
+};
+
+#[derive(Serialize)]
+struct CommandReply {
+    event: Event,
+    accepted: bool,
+    reply: Option<Reply>,
+    reason: Option<String>,
+    status: DoorStatus,
+}
+
+async fn door_status(State(state): State<Arc<AppState>>) -> Json<DoorStatus> {
+    Json(state.door_monitor.status())
+}
+
+/// `?limit=&source=radar&since=&until=&acted=`, times in ms since epoch
+async fn door_audit(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Json<Vec<AuditEntry>> {
+    Json(state.audit.query(&query))
+}
+
+/// the stored endstop measurement, null while the config steps are used
+async fn door_calibration(State(state): State<Arc<AppState>>) -> Json<Option<Calibration>> {
+    Json(state.door_monitor.calibration())
+}
+
+async fn door_stats(State(state): State<Arc<AppState>>) -> Json<DoorStats> {
+    Json(state.stats.get())
+}
+
+#[derive(Serialize)]
+struct ProfileReply {
+    accepted: bool,
+    reason: Option<String>,
+    profile: ProfileStatus,
+}
+
+async fn door_profile(State(state): State<Arc<AppState>>) -> Json<ProfileStatus> {
+    Json(state.profiles.status())
+}
+
+fn profile_reply(
+    state: &AppState,
+    result: Result<ProfileStatus, ProfileError>,
+) -> (StatusCode, Json<ProfileReply>) {
+    let (code, reason) = match &result {
+        Ok(_) => (StatusCode::OK, None),
+        Err(e @ ProfileError::Unknown(_)) => (StatusCode::NOT_FOUND, Some(e.to_string())),
+        Err(e @ ProfileError::Invalid(_)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Some(e.to_string()))
+        }
+    };
+    let reply = ProfileReply {
+        accepted: reason.is_none(),
+        reason,
+        profile: result.unwrap_or_else(|_| state.profiles.status()),
+    };
+    (code, Json(reply))
+}
+
+/// the whole `MotionConfig` as JSON, applies from the next move on
+async fn set_profile(
+    State(state): State<Arc<AppState>>,
+    Json(motion): Json<MotionConfig>,
+) -> (StatusCode, Json<ProfileReply>) {
+    profile_reply(&state, state.profiles.set(motion))
+}
+
+/// one of `door.profiles` or "default"
+async fn switch_profile(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<ProfileReply>) {
+    profile_reply(&state, state.profiles.switch(&name))
+}
+
+#[derive(Serialize)]
+struct AutomationReply {
+    accepted: bool,
+    reason: Option<String>,
+    status: DoorStatus,
+}
+
+/// `auto`, `manual` or `entry`, limits the radar only, commands keep working
+async fn set_automation(
+    State(state): State<Arc<AppState>>,
+    Path(mode): Path<AutomationMode>,
+) -> (StatusCode, Json<AutomationReply>) {
+    let (code, reason) = match mode {
+        AutomationMode::Entry if !state.entry_radars => (
+            StatusCode::CONFLICT,
+            Some("no radar is listed in radar.entry_uarts".to_string()),
+        ),
+        _ => {
+            state.door_monitor.set_automation(mode);
+            (StatusCode::OK, None)
+        }
+    };
+    let reply = AutomationReply {
+        accepted: reason.is_none(),
+        reason,
+        status: state.door_monitor.status(),
+    };
+    (code, Json(reply))
+}
+
+/// Waits for the controller to decide, moves answer before they are done
+async fn command(state: &AppState, event: Event, route: &str) -> (StatusCode, Json<CommandReply>) {
+    if let Some(problem) = event.problem() {
+        let reply = CommandReply {
+            event,
+            accepted: false,
+            reply: None,
+            reason: Some(problem.to_string()),
+            status: state.door_monitor.status(),
+        };
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(reply));
+    }
+    let source = Source::Http {
+        route: route.to_string(),
+    };
+    let reply = state.door.command(event.clone(), source).await.ok();
+    let (code, reason) = match &reply {
+        Some(Reply::Completed(_)) => (StatusCode::OK, None),
+        Some(Reply::Accepted(_)) => (StatusCode::ACCEPTED, None),
+        Some(Reply::Rejected(door::State::Undefined)) => (
+            StatusCode::CONFLICT,
+            Some(format!(
+                "{:?} is not possible while the door position is unknown, calibrate first",
+                event
+            )),
+        ),
+        Some(Reply::Rejected(door::State::EmergencyStop)) if event == Event::Reset => (
+            StatusCode::CONFLICT,
+            Some("the emergency stop button is still pressed, release it first".to_string()),
+        ),
+        Some(Reply::Rejected(s)) => (
+            StatusCode::CONFLICT,
+            Some(format!("{:?} is not possible while {:?}", event, s)),
+        ),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Some("door controller is not running".to_string()),
+        ),
+    };
+    let reply = CommandReply {
+        event,
+        accepted: reason.is_none(),
+        reply,
+        reason,
+        status: state.door_monitor.status(),
+    };
+    (code, Json(reply))
+}
+
+#[derive(Deserialize)]
+struct OpenQuery {
+    width_cm: Option<f32>,
+}
+
+/// `?width_cm=`, without it the door opens to `door.open_distance_cm`
+async fn open(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OpenQuery>,
+) -> (StatusCode, Json<CommandReply>) {
+    let event = Event::Open {
+        width_cm: query.width_cm,
+    };
+    command(&state, event, "open").await
+}
+
+#[derive(Deserialize)]
+struct HoldQuery {
+    secs: Option<f32>,
+}
+
+/// `?secs=`, without it the door is held for `door.hold_max_secs`
+async fn hold(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HoldQuery>,
+) -> (StatusCode, Json<CommandReply>) {
+    command(&state, Event::Hold { secs: query.secs }, "hold").await
+}
+
+#[derive(Deserialize)]
+struct MeasureQuery {
+    passes: Option<u32>,
+}
+
+/// `?passes=`, progress and result come as `DoorNotice` over WS
+async fn measure(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MeasureQuery>,
+) -> (StatusCode, Json<CommandReply>) {
+    let event = Event::MeasureEndstops {
+        passes: query.passes,
+    };
+    command(&state, event, "measure").await
+}
 
 macro_rules! door_handlers {
     ($($name:ident => $event:ident),*) => {
         $(
-            async fn $name(State(state): State<Arc<AppState>>) {
-                state.door.send(door::door::Event::$event).unwrap();
+            async fn $name(State(state): State<Arc<AppState>>) -> (StatusCode, Json<CommandReply>) {
+                command(&state, Event::$event, stringify!($name)).await
             }
         )*
 
         pub fn door_routes() -> Router<Arc<AppState>> {
             Router::new()
-                $(.route(concat!("/", stringify!($name)), get($name)))*
+                .route("/", get(door_status))
+                .route("/audit", get(door_audit))
+                .route("/stats", get(door_stats))
+                .route("/calibration", get(door_calibration))
+                .route("/profile", get(door_profile).put(set_profile))
+                .route("/profile/{name}", post(switch_profile))
+                .route("/automation/{mode}", post(set_automation))
+                .route("/measure", post(measure))
+                .route("/open", post(open))
+                .route("/hold", post(hold))
+                $(.route(concat!("/", stringify!($name)), post($name)))*
         }
     };
 }
 
 door_handlers! {
-    open    => Open,
     close   => Close,
-    hold    => Hold,
     release => Release,
     lock    => Lock,
     unlock  => Unlock,
-    calibrate  => Calibrate
+    calibrate  => Calibrate,
+    emergency_stop => EmergencyStop,
+    reset   => Reset
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        door::{audit::AuditLog, controller::start_door_controller, stats::StatsStore},
+        led::stripe::Stripe,
+        sim::Simulation,
+    };
+
+    fn app_state() -> Arc<AppState> {
+        let sim = Simulation::start(&Config::default(), None).unwrap();
+        let door = sim.door.door;
+        let (door_monitor, profiles) = {
+            let door = door.lock().unwrap();
+            (door.get_monitor(), door.get_profiles())
+        };
+        let stats = StatsStore::in_memory(door_monitor.cm_per_step());
+        let audit = AuditLog::in_memory();
+        let stripe = Stripe::new(Box::new(sim.leds), 10);
+        Arc::new(AppState {
+            led_repeat: stripe.get_running_clone(),
+            led_stripe: Arc::new(Mutex::new(stripe)),
+            door: start_door_controller(door, audit.clone(), stats.clone()),
+            door_monitor,
+            audit,
+            stats,
+            profiles,
+            entry_radars: false,
+            tx: broadcast::channel(8).0,
+        })
+    }
+
+    #[tokio::test]
+    async fn invalid_widths_and_durations_are_refused() {
+        let state = app_state();
+        for value in [-5.0, 0.0, f32::NAN, f32::INFINITY] {
+            let query = OpenQuery {
+                width_cm: Some(value),
+            };
+            let (code, Json(reply)) = open(State(state.clone()), Query(query)).await;
+            assert_eq!(code, StatusCode::UNPROCESSABLE_ENTITY, "width {}", value);
+            assert!(!reply.accepted);
+
+            let query = HoldQuery { secs: Some(value) };
+            let (code, _) = hold(State(state.clone()), Query(query)).await;
+            assert_eq!(code, StatusCode::UNPROCESSABLE_ENTITY, "secs {}", value);
+        }
+        assert!(state.audit.query(&AuditQuery::default()).is_empty());
+
+        // a valid width gets to the controller, which wants a calibration first
+        let query = OpenQuery {
+            width_cm: Some(30.0),
+        };
+        let (code, _) = open(State(state.clone()), Query(query)).await;
+        assert_eq!(code, StatusCode::CONFLICT);
+    }
 }
@@ -0,0 +1,126 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Wear counters of the door, kept over restarts for maintenance planning
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoorStats {
+    /// opened and closed again
+    pub cycles: u64,
+    /// motor steps in either direction, calibrations included
+    pub travel_steps: u64,
+    pub travel_cm: f64,
+    pub moves: u64,
+    /// turned around, stopped short or halted
+    pub cancelled_moves: u64,
+    pub move_ms_total: u64,
+    pub move_ms_max: u64,
+    pub last_move_ms: Option<u64>,
+    pub calibrations: u64,
+    /// passes of the middle endstop that corrected the step counter
+    pub drift_corrections: u64,
+    /// ms since epoch when counting started
+    pub since: u64,
+}
+impl DoorStats {
+    pub fn record_move(&mut self, move_ms: u64) {
+        self.moves += 1;
+        self.move_ms_total += move_ms;
+        self.move_ms_max = self.move_ms_max.max(move_ms);
+        self.last_move_ms = Some(move_ms);
+    }
+}
+
+type Listener = Box<dyn Fn(&DoorStats) + Send>;
+
+struct Inner {
+    path: Option<PathBuf>,
+    cm_per_step: f32,
+    stats: DoorStats,
+    listeners: Vec<Listener>,
+}
+
+/// `DoorStats` written to a JSON file after every change
+#[derive(Clone)]
+pub struct StatsStore(Arc<Mutex<Inner>>);
+impl StatsStore {
+    /// Continues the counters of an existing file
+    pub fn open<P: AsRef<Path>>(path: P, cm_per_step: f32) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let stats = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DoorStats {
+                since: now_ms(),
+                ..Default::default()
+            },
+            Err(e) => return Err(e),
+        };
+        Ok(Self::with_path(Some(path), cm_per_step, stats))
+    }
+    /// Counts since start only
+    pub fn in_memory(cm_per_step: f32) -> Self {
+        let stats = DoorStats {
+            since: now_ms(),
+            ..Default::default()
+        };
+        Self::with_path(None, cm_per_step, stats)
+    }
+    fn with_path(path: Option<PathBuf>, cm_per_step: f32, stats: DoorStats) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            path,
+            cm_per_step,
+            stats,
+            listeners: Vec::new(),
+        })))
+    }
+
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: Fn(&DoorStats) + Send + 'static,
+    {
+        self.0.lock().unwrap().listeners.push(Box::new(listener));
+    }
+
+    pub fn get(&self) -> DoorStats {
+        self.0.lock().unwrap().stats.clone()
+    }
+
+    /// Applies `change`, saves and notifies the listeners
+    pub fn update<F>(&self, change: F)
+    where
+        F: FnOnce(&mut DoorStats),
+    {
+        let mut inner = self.0.lock().unwrap();
+        change(&mut inner.stats);
+        inner.stats.travel_cm = inner.stats.travel_steps as f64 * inner.cm_per_step as f64;
+        if let Some(path) = &inner.path
+            && let Err(e) = save(path, &inner.stats)
+        {
+            println!("Door stats write failed: {}", e);
+        }
+        for l in inner.listeners.iter() {
+            l(&inner.stats);
+        }
+    }
+}
+
+/// through a temporary file, a crash never leaves half a file behind
+fn save(path: &Path, stats: &DoorStats) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(stats).unwrap())?;
+    fs::rename(tmp, path)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
@@ -2,16 +2,29 @@ const COOLDOWN_TIME: Duration = Duration::from_millis(500);
 
 use std::{
     sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicI64, Ordering},
         mpsc::{Sender, channel},
     },
     thread::{self},
     time::{Duration, Instant},
 };
 
-use rppal::gpio::{Error, Gpio, OutputPin};
+use serde::Deserialize;
 
+use crate::{
+    config::StepperConfig,
+    door::{
+        motion::{MotionPlan, next_level},
+        motor::MotorObserver,
+    },
+    hal::{Error, Hal, InputPin, Level, OutputPin},
+};
+
+/// Microstep settings of the driver DIP switches for a 200 step motor,
+/// read from the pulse count in the config
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "u16")]
 pub enum PulsePerRotation {
     PPR200,
     PPR800,
@@ -19,65 +32,184 @@ pub enum PulsePerRotation {
     PPR3200,
     PPR6400,
 }
+impl PulsePerRotation {
+    pub fn pulses(self) -> u16 {
+        match self {
+            PulsePerRotation::PPR200 => 200,
+            PulsePerRotation::PPR800 => 800,
+            PulsePerRotation::PPR1600 => 1600,
+            PulsePerRotation::PPR3200 => 3200,
+            PulsePerRotation::PPR6400 => 6400,
+        }
+    }
+    /// pulses per full step of the motor
+    pub fn microsteps(self) -> u16 {
+        self.pulses() / 200
+    }
+}
+impl TryFrom<u16> for PulsePerRotation {
+    type Error = String;
+    fn try_from(pulses: u16) -> Result<Self, Self::Error> {
+        match pulses {
+            200 => Ok(PulsePerRotation::PPR200),
+            800 => Ok(PulsePerRotation::PPR800),
+            1600 => Ok(PulsePerRotation::PPR1600),
+            3200 => Ok(PulsePerRotation::PPR3200),
+            6400 => Ok(PulsePerRotation::PPR6400),
+            _ => Err(format!(
+                "{} pulses per rotation is no driver setting, use 200, 800, 1600, 3200 or 6400",
+                pulses
+            )),
+        }
+    }
+}
+
+struct MoveTarget {
+    step: i64,
+    active: bool,
+    /// brake at `HARD_STOP_LEVELS` until the carriage stands
+    hard: bool,
+}
+
+/// Changes the target of a running `turn_to`
+#[derive(Clone)]
+pub struct Retarget(Arc<Mutex<MoveTarget>>);
+impl Retarget {
+    /// false when no move is running, the new target is dropped then
+    pub fn set(&self, step: i64) -> bool {
+        self.change(step, false)
+    }
+    /// `set` that stops hard before heading for the new target, for
+    /// someone in the way of the door
+    pub fn reverse(&self, step: i64) -> bool {
+        self.change(step, true)
+    }
+    fn change(&self, step: i64, hard: bool) -> bool {
+        let mut target = self.0.lock().unwrap();
+        if target.active {
+            target.step = step;
+            target.hard = hard;
+        }
+        target.active
+    }
+}
+
+/// Input that is released when the counter reaches `step` in positive
+/// direction, `turn_to_synced` corrects lost steps with it on the way
+pub struct SyncPoint<'a> {
+    pub pin: &'a dyn InputPin,
+    pub step: i64,
+}
+
+/// Messages to the ENA watchdog
+enum Drive {
+    On,
+    /// de-energize after `COOLDOWN_TIME` unless turned on again
+    Idle,
+    Off,
+}
+
+/// Stops the stepper at once without braking and cuts the driver.
+/// Every move is refused until `release`.
+#[derive(Clone)]
+pub struct Halt {
+    halted: Arc<AtomicBool>,
+    drive: Sender<Drive>,
+}
+impl Halt {
+    pub fn trigger(&self) {
+        self.halted.store(true, Ordering::SeqCst);
+        let _ = self.drive.send(Drive::Off);
+    }
+    pub fn release(&self) {
+        self.halted.store(false, Ordering::SeqCst);
+    }
+    pub fn is_halted(&self) -> bool {
+        self.halted.load(Ordering::SeqCst)
+    }
+}
 
 pub struct Stepper {
     // ena: Arc<Mutex<OutputPin>>,
-    pub dir: OutputPin,
-    pub step: OutputPin,
+    pub dir: Box<dyn OutputPin>,
+    pub step: Box<dyn OutputPin>,
     // steps_per_rot: u32,
-    tx: Sender<bool>,
-    step_counter: i64,
+    tx: Sender<Drive>,
+    halted: Arc<AtomicBool>,
+    step_counter: Arc<AtomicI64>,
+    /// pulses since start in either direction
+    travel: u64,
     canceler: Arc<AtomicBool>,
-    steps_per_rot: u16,
+    target: Arc<Mutex<MoveTarget>>,
+    ppr: PulsePerRotation,
     wheel_size: f32,
-    min_freq: f32,
-    max_freq: f32,
-    startup_steps: i64,
+    observers: Vec<Box<dyn MotorObserver>>,
+    observe_every: Duration,
+    observed_at: Instant,
+    plan: MotionPlan,
+    ramp: Vec<Duration>,
 }
 
 impl Stepper {
-    pub fn new(
-        ena: u8,
-        dir: u8,
-        step: u8,
-        steps_per_rot: u16,
-        wheel_size: f32,
-    ) -> Result<Self, Error> {
+    pub fn new(hal: &dyn Hal, config: &StepperConfig) -> Result<Self, Error> {
+        let plan = MotionPlan::new(&config.motion, |cm| {
+            to_steps(cm, config.wheel_size_cm, config.steps_per_rotation)
+        });
+        let halted = Arc::new(AtomicBool::new(false));
         let t = Self {
             // ena: a.clone(),
-            dir: Gpio::new()?.get(dir)?.into_output_low(),
-            step: Gpio::new()?.get(step)?.into_output_low(),
-            tx: Stepper::spawn_watchdog(Gpio::new()?.get(ena)?.into_output_high()),
-            step_counter: 0,
+            dir: hal.output(config.dir_pin, Level::Low)?,
+            step: hal.output(config.step_pin, Level::Low)?,
+            tx: Stepper::spawn_watchdog(hal.output(config.ena_pin, Level::High)?, halted.clone()),
+            halted,
+            step_counter: Arc::new(AtomicI64::new(0)),
+            travel: 0,
             canceler: Arc::new(AtomicBool::new(false)),
-            steps_per_rot: steps_per_rot,
-            wheel_size: wheel_size,
-            min_freq: 300.0,
-            max_freq: 25000.0,
-            startup_steps: 2900,
+            target: Arc::new(Mutex::new(MoveTarget {
+                step: 0,
+                active: false,
+                hard: false,
+            })),
+            ppr: config.steps_per_rotation,
+            wheel_size: config.wheel_size_cm,
+            observers: Vec::new(),
+            observe_every: Duration::from_millis(config.position_update_ms),
+            observed_at: Instant::now(),
+            ramp: plan.ramp(),
+            plan,
         };
         Ok(t)
     }
 
-    fn spawn_watchdog(mut ena_pin: OutputPin) -> Sender<bool> {
+    /// Keeps the driver off while halted, whatever the stepping code sends
+    fn spawn_watchdog(mut ena_pin: Box<dyn OutputPin>, halted: Arc<AtomicBool>) -> Sender<Drive> {
         let (tx, rx) = channel();
 
         thread::spawn(move || {
             while let Ok(signal) = rx.recv() {
-                if signal {
-                    ena_pin.set_low();
-                    continue;
+                match signal {
+                    // a halt can land between a caller's check and its `On`
+                    Drive::On if halted.load(Ordering::SeqCst) => continue,
+                    Drive::On => {
+                        ena_pin.set_low();
+                        continue;
+                    }
+                    Drive::Off => {
+                        ena_pin.set_high();
+                        continue;
+                    }
+                    Drive::Idle => {}
                 }
 
                 loop {
                     match rx.recv_timeout(COOLDOWN_TIME) {
-                        Ok(true) => {
+                        Ok(Drive::On) => {
                             break;
                         }
-                        Ok(false) => {
+                        Ok(Drive::Idle) => {
                             continue;
                         }
-                        Err(_) => {
+                        Ok(Drive::Off) | Err(_) => {
                             ena_pin.set_high();
                             break;
                         }
@@ -87,14 +219,58 @@ impl Stepper {
         });
         tx
     }
+    pub fn get_halt(&self) -> Halt {
+        Halt {
+            halted: self.halted.clone(),
+            drive: self.tx.clone(),
+        }
+    }
+    pub fn is_halted(&self) -> bool {
+        self.halted.load(Ordering::SeqCst)
+    }
+    pub fn set_plan(&mut self, plan: MotionPlan) {
+        self.ramp = plan.ramp();
+        self.plan = plan;
+    }
+    pub fn get_plan(&self) -> &MotionPlan {
+        &self.plan
+    }
     pub fn get_cancler_clone(&self) -> Arc<AtomicBool> {
         self.canceler.clone()
     }
+    pub fn get_retarget_clone(&self) -> Retarget {
+        Retarget(self.target.clone())
+    }
+    /// shared step counter, readable while a move holds the stepper
+    pub fn get_step_counter_clone(&self) -> Arc<AtomicI64> {
+        self.step_counter.clone()
+    }
     pub fn get_step_count(&self) -> i64 {
-        self.step_counter
+        self.step_counter.load(Ordering::SeqCst)
+    }
+    pub fn get_travel(&self) -> u64 {
+        self.travel
     }
     pub fn get_steps(&self, distance_in_cm: f32) -> i64 {
-        (distance_in_cm / self.wheel_size * self.steps_per_rot as f32) as i64
+        to_steps(distance_in_cm, self.wheel_size, self.ppr)
+    }
+    pub fn get_distance(&self, steps: i64) -> f32 {
+        steps as f32 / self.ppr.pulses() as f32 * self.wheel_size
+    }
+    /// Observers are called from the stepping thread, at most every
+    /// `position_update_ms` while moving and once where a move ends
+    pub fn add_observer(&mut self, observer: Box<dyn MotorObserver>) {
+        self.observers.push(observer);
+    }
+    fn observe(&mut self, force: bool) {
+        if !force && self.observed_at.elapsed() < self.observe_every {
+            return;
+        }
+        self.observed_at = Instant::now();
+        let step = self.get_step_count();
+        for o in self.observers.iter_mut() {
+            o.on_step(step);
+        }
     }
     pub fn get_fmax(&self, distance_in_cm: f32, time: f32) -> f32 {
         (self.get_steps(distance_in_cm) as f32 / time) * 2.0
@@ -115,103 +291,171 @@ impl Stepper {
         // let high = Duration::from_secs_f32(1.0 / self.start_freq);
         // let low = Duration::from_secs()
         let dur = Duration::from_secs_f32(1.0 / (freq * 2.0));
-        self.tx.send(true).expect("send failed true");
-        while condition() {
+        if self.is_halted() {
+            return;
+        }
+        self.tx.send(Drive::On).expect("send failed true");
+        while condition() && !self.is_halted() {
             self.step.set_high();
             sleeper.sleep(dur);
 
-            self.step_counter += step_delta;
+            self.step_counter.fetch_add(step_delta, Ordering::SeqCst);
+            self.travel += 1;
 
             self.step.set_low();
             sleeper.sleep(dur);
+            self.observe(false);
         }
-        self.tx.send(false).expect("send failed false");
+        self.observe(true);
+        self.tx.send(Drive::Idle).expect("send failed false");
         // sleeper.sleep(Duration::from_millis(50));
     }
 
+    /// Moves to `step` on the motion ramp. The target can be changed on the
+    /// way with `Retarget`, the stepper then brakes, reverses if needed and
+    /// speeds up again without stopping in between. The canceler brakes to a
+    /// stop wherever the carriage is.
     pub fn turn_to(&mut self, step: i64) {
+        self.run_to(step, None);
+    }
+    /// `turn_to` that re-syncs the counter whenever the carriage passes
+    /// `sync`. Returns the deviation found at every pass.
+    pub fn turn_to_synced(&mut self, step: i64, sync: SyncPoint) -> Vec<i64> {
+        self.run_to(step, Some(sync))
+    }
+    fn run_to(&mut self, step: i64, sync: Option<SyncPoint>) -> Vec<i64> {
         let start = Instant::now();
-        let do_steps = step - self.step_counter;
-        if do_steps == 0 {
-            return;
+        let mut deviations = Vec::new();
+        if self.is_halted() {
+            return deviations;
         }
-
-        let step_delta: i64 = do_steps.signum();
-
-        if do_steps > 0 {
-            self.dir.set_high();
-        } else {
-            self.dir.set_low();
+        let mut sync_level = sync.as_ref().map(|s| s.pin.read());
+        {
+            let mut target = self.target.lock().unwrap();
+            target.step = step;
+            target.active = true;
+            target.hard = false;
         }
 
         let sleeper = spin_sleep::SpinSleeper::new(0);
-        let do_steps_abs = do_steps.abs();
-        let mut c = 0;
-        let mut istep = 0;
-        let _ = self.tx.send(true);
-        while self.step_counter != step && !self.canceler.load(Ordering::SeqCst) {
-            istep = c.min(do_steps_abs - c);
-
-            let freq = if istep > self.startup_steps {
-                self.max_freq
-            } else {
-                linear_growth(istep, self.min_freq, self.max_freq, self.startup_steps)
-                // logistic_growth(step, self.start_freq, self.max_freq, self.startup_steps)
+        let max_level = self.ramp.len() - 1;
+        // direction and ramp level of the last pulse, None while standing
+        let mut motion: Option<(i64, usize)> = None;
+        let _ = self.tx.send(Drive::On);
+        loop {
+            if self.is_halted() {
+                self.target.lock().unwrap().active = false;
+                break;
+            }
+            let cancelled = self.canceler.load(Ordering::SeqCst);
+            let (dir, level) = match motion {
+                None => {
+                    let mut target = self.target.lock().unwrap();
+                    target.hard = false;
+                    let remaining = target.step - self.get_step_count();
+                    if remaining == 0 || cancelled {
+                        target.active = false;
+                        break;
+                    }
+                    let dir = remaining.signum();
+                    if dir > 0 {
+                        self.dir.set_high();
+                    } else {
+                        self.dir.set_low();
+                    }
+                    (dir, 0)
+                }
+                Some((dir, level)) => {
+                    let (ahead, hard) = {
+                        let target = self.target.lock().unwrap();
+                        ((target.step - self.get_step_count()) * dir, target.hard)
+                    };
+                    match next_level(level, max_level, ahead, cancelled, hard) {
+                        Some(level) => (dir, level),
+                        None => {
+                            motion = None;
+                            continue;
+                        }
+                    }
+                }
             };
+            self.pulse(dir, self.ramp[level], &sleeper);
+            motion = Some((dir, level));
 
-            let dur = Duration::from_secs_f32(1.0 / (freq * 2.0));
-
-            self.step.set_high();
-            sleeper.sleep(dur);
-
-            self.step_counter += step_delta;
-
-            self.step.set_low();
-            sleeper.sleep(dur);
-
-            c += 1;
-        }
-
-        for i in (0..(istep - 1).min(self.startup_steps)).rev() {
-            let freq =
-                linear_growth(i, self.min_freq, self.max_freq, self.startup_steps)
-                // logistic_growth(step, self.start_freq, self.max_freq, self.startup_steps)
-            ;
-
-            let dur = Duration::from_secs_f32(1.0 / (freq * 2.0));
-
-            self.step.set_high();
-            sleeper.sleep(dur);
-
-            self.step_counter += step_delta;
-
-            self.step.set_low();
-            sleeper.sleep(dur);
+            if let Some(sync) = &sync {
+                let now = sync.pin.read();
+                // only the release edge is at the calibrated step, the
+                // counter is one below it when the switch is hit from above
+                let expected = match (sync_level == Some(now), dir > 0, now) {
+                    (false, true, Level::High) => Some(sync.step),
+                    (false, false, Level::Low) => Some(sync.step - 1),
+                    _ => None,
+                };
+                if let Some(expected) = expected {
+                    deviations.push(self.get_step_count() - expected);
+                    self.set_step_count(expected);
+                }
+                sync_level = Some(now);
+            }
         }
 
-        let _ = self.tx.send(false);
+        self.observe(true);
+        let _ = self.tx.send(Drive::Idle);
         self.canceler.store(false, Ordering::SeqCst);
         println!("time {}ms", start.elapsed().as_millis());
         sleeper.sleep(Duration::from_millis(50));
+        deviations
+    }
+
+    fn pulse(&mut self, step_delta: i64, period: Duration, sleeper: &spin_sleep::SpinSleeper) {
+        let dur = period / 2;
+        self.step.set_high();
+        sleeper.sleep(dur);
+
+        self.step_counter.fetch_add(step_delta, Ordering::SeqCst);
+        self.travel += 1;
+
+        self.step.set_low();
+        sleeper.sleep(dur);
+        self.observe(false);
     }
 
     pub fn reset_step_count(&mut self) {
-        self.step_counter = 0;
+        self.set_step_count(0);
     }
     pub fn set_step_count(&mut self, steps: i64) {
-        self.step_counter = steps;
+        self.step_counter.store(steps, Ordering::SeqCst);
     }
 }
-#[inline]
-fn logistic_growth(step: i64, start_freq: f32, max_freq: f32, startup_steps: i64) -> f32 {
-    let k = 0.1; // Wachstumsrate, anpassen für gewünschte Steilheit
-    let x0 = startup_steps as f32 / 2.0; // Wendepunkt in der Mitte der Startup-Phase
-
-    let x = step as f32;
-    start_freq + (max_freq - start_freq) / (1.0 + (-k * (x - x0)).exp())
+fn to_steps(distance_in_cm: f32, wheel_size: f32, ppr: PulsePerRotation) -> i64 {
+    (distance_in_cm / wheel_size * ppr.pulses() as f32) as i64
 }
-#[inline]
-fn linear_growth(step: i64, start_freq: f32, max_freq: f32, startup_steps: i64) -> f32 {
-    let m = (max_freq - start_freq) / startup_steps as f32;
-    step as f32 * m + start_freq
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::mock::MockHal;
+
+    #[test]
+    fn halt_keeps_the_driver_off() {
+        let hal = MockHal::new();
+        let config = StepperConfig::default();
+        let stepper = Stepper::new(&hal, &config).unwrap();
+        let settle = || thread::sleep(Duration::from_millis(20));
+
+        stepper.tx.send(Drive::On).unwrap();
+        settle();
+        assert_eq!(hal.level(config.ena_pin), Some(Level::Low));
+
+        stepper.get_halt().trigger();
+        // as if a move passed its halt check just before the trigger
+        stepper.tx.send(Drive::On).unwrap();
+        settle();
+        assert_eq!(hal.level(config.ena_pin), Some(Level::High));
+
+        stepper.get_halt().release();
+        stepper.tx.send(Drive::On).unwrap();
+        settle();
+        assert_eq!(hal.level(config.ena_pin), Some(Level::Low));
+    }
 }
@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::hal::{Error, Hal, I2cBus, InputPin, Level, OutputPin, Pull, PwmPin, SpiBus};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub pin: u8,
+    pub level: Level,
+    pub at: Duration,
+}
+
+type Listener = Box<dyn FnMut(&Edge, &mut MockPins) + Send>;
+
+/// In memory pin state shared by all pins of a `MockHal`.
+/// Listeners see every output edge and may change input levels,
+/// that is how simulated hardware reacts to the program.
+pub struct MockPins {
+    start: Instant,
+    levels: HashMap<u8, Level>,
+    claimed: HashSet<u8>,
+    pwm: HashMap<u8, (f64, f64)>,
+    edges: Vec<Edge>,
+    recording: bool,
+    listeners: Vec<Listener>,
+    i2c_address: u16,
+    i2c_registers: HashMap<(u16, u8), Vec<u8>>,
+    i2c_writes: Vec<(u16, u8, Vec<u8>)>,
+    spi_writes: Vec<Vec<u8>>,
+}
+impl MockPins {
+    pub fn set_input(&mut self, pin: u8, level: Level) {
+        self.levels.insert(pin, level);
+    }
+    pub fn level(&self, pin: u8) -> Option<Level> {
+        self.levels.get(&pin).copied()
+    }
+    fn write(&mut self, pin: u8, level: Level) {
+        if self.levels.insert(pin, level) == Some(level) {
+            return;
+        }
+        let edge = Edge {
+            pin,
+            level,
+            at: self.start.elapsed(),
+        };
+        if self.recording {
+            self.edges.push(edge);
+        }
+        let mut listeners = std::mem::take(&mut self.listeners);
+        for l in listeners.iter_mut() {
+            l(&edge, self);
+        }
+        listeners.append(&mut self.listeners);
+        self.listeners = listeners;
+    }
+}
+
+#[derive(Clone)]
+pub struct MockHal {
+    pins: Arc<Mutex<MockPins>>,
+}
+impl Default for MockHal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl MockHal {
+    pub fn new() -> Self {
+        Self {
+            pins: Arc::new(Mutex::new(MockPins {
+                start: Instant::now(),
+                levels: HashMap::new(),
+                claimed: HashSet::new(),
+                pwm: HashMap::new(),
+                edges: Vec::new(),
+                recording: true,
+                listeners: Vec::new(),
+                i2c_address: 0,
+                i2c_registers: HashMap::new(),
+                i2c_writes: Vec::new(),
+                spi_writes: Vec::new(),
+            })),
+        }
+    }
+    /// Edge recording grows without bound, long running simulations turn it off
+    pub fn set_recording(&self, recording: bool) {
+        self.pins.lock().unwrap().recording = recording;
+    }
+    pub fn on_edge<F>(&self, listener: F)
+    where
+        F: FnMut(&Edge, &mut MockPins) + Send + 'static,
+    {
+        self.pins.lock().unwrap().listeners.push(Box::new(listener));
+    }
+    pub fn set_input(&self, pin: u8, level: Level) {
+        self.pins.lock().unwrap().set_input(pin, level);
+    }
+    pub fn level(&self, pin: u8) -> Option<Level> {
+        self.pins.lock().unwrap().level(pin)
+    }
+    pub fn pwm_state(&self, pin: u8) -> Option<(f64, f64)> {
+        self.pins.lock().unwrap().pwm.get(&pin).copied()
+    }
+    pub fn edges(&self) -> Vec<Edge> {
+        self.pins.lock().unwrap().edges.clone()
+    }
+    pub fn take_edges(&self) -> Vec<Edge> {
+        std::mem::take(&mut self.pins.lock().unwrap().edges)
+    }
+    pub fn rising_edges(&self, pin: u8) -> usize {
+        self.pins
+            .lock()
+            .unwrap()
+            .edges
+            .iter()
+            .filter(|e| e.pin == pin && e.level == Level::High)
+            .count()
+    }
+    pub fn set_i2c_register(&self, address: u16, register: u8, block: &[u8]) {
+        self.pins
+            .lock()
+            .unwrap()
+            .i2c_registers
+            .insert((address, register), block.to_vec());
+    }
+    pub fn i2c_writes(&self) -> Vec<(u16, u8, Vec<u8>)> {
+        self.pins.lock().unwrap().i2c_writes.clone()
+    }
+    pub fn spi_writes(&self) -> Vec<Vec<u8>> {
+        self.pins.lock().unwrap().spi_writes.clone()
+    }
+    fn claim(&self, pin: u8) -> Result<(), Error> {
+        match self.pins.lock().unwrap().claimed.insert(pin) {
+            true => Ok(()),
+            false => Err(Error::PinUsed(pin)),
+        }
+    }
+}
+
+struct MockOutput {
+    pin: u8,
+    pins: Arc<Mutex<MockPins>>,
+}
+struct MockInput {
+    pin: u8,
+    pull: Pull,
+    pins: Arc<Mutex<MockPins>>,
+}
+struct MockI2c {
+    pins: Arc<Mutex<MockPins>>,
+}
+struct MockSpi {
+    pins: Arc<Mutex<MockPins>>,
+}
+
+impl OutputPin for MockOutput {
+    fn write(&mut self, level: Level) {
+        self.pins.lock().unwrap().write(self.pin, level);
+    }
+}
+impl PwmPin for MockOutput {
+    fn set_pwm_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<(), Error> {
+        self.pins
+            .lock()
+            .unwrap()
+            .pwm
+            .insert(self.pin, (frequency, duty_cycle));
+        Ok(())
+    }
+    fn clear_pwm(&mut self) -> Result<(), Error> {
+        self.pins.lock().unwrap().pwm.remove(&self.pin);
+        Ok(())
+    }
+}
+impl Drop for MockOutput {
+    fn drop(&mut self) {
+        self.pins.lock().unwrap().claimed.remove(&self.pin);
+    }
+}
+impl InputPin for MockInput {
+    fn read(&self) -> Level {
+        match self.pins.lock().unwrap().level(self.pin) {
+            Some(level) => level,
+            None if self.pull == Pull::Up => Level::High,
+            None => Level::Low,
+        }
+    }
+}
+impl Drop for MockInput {
+    fn drop(&mut self) {
+        self.pins.lock().unwrap().claimed.remove(&self.pin);
+    }
+}
+impl I2cBus for MockI2c {
+    fn set_slave_address(&mut self, address: u16) -> Result<(), Error> {
+        self.pins.lock().unwrap().i2c_address = address;
+        Ok(())
+    }
+    fn block_write(&mut self, register: u8, block: &[u8]) -> Result<(), Error> {
+        let mut pins = self.pins.lock().unwrap();
+        let address = pins.i2c_address;
+        pins.i2c_writes.push((address, register, block.to_vec()));
+        pins.i2c_registers
+            .insert((address, register), block.to_vec());
+        Ok(())
+    }
+    fn block_read(&mut self, register: u8, block: &mut [u8]) -> Result<(), Error> {
+        let pins = self.pins.lock().unwrap();
+        let stored = pins.i2c_registers.get(&(pins.i2c_address, register));
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = stored.and_then(|s| s.get(i)).copied().unwrap_or(0);
+        }
+        Ok(())
+    }
+}
+impl SpiBus for MockSpi {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pins.lock().unwrap().spi_writes.push(data.to_vec());
+        Ok(data.len())
+    }
+}
+
+impl Hal for MockHal {
+    fn output(&self, pin: u8, initial: Level) -> Result<Box<dyn OutputPin>, Error> {
+        self.claim(pin)?;
+        self.pins.lock().unwrap().levels.insert(pin, initial);
+        Ok(Box::new(MockOutput {
+            pin,
+            pins: self.pins.clone(),
+        }))
+    }
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn InputPin>, Error> {
+        self.claim(pin)?;
+        Ok(Box::new(MockInput {
+            pin,
+            pull,
+            pins: self.pins.clone(),
+        }))
+    }
+    fn pwm(&self, pin: u8) -> Result<Box<dyn PwmPin>, Error> {
+        self.claim(pin)?;
+        self.pins.lock().unwrap().levels.insert(pin, Level::Low);
+        Ok(Box::new(MockOutput {
+            pin,
+            pins: self.pins.clone(),
+        }))
+    }
+    fn i2c(&self) -> Result<Box<dyn I2cBus>, Error> {
+        Ok(Box::new(MockI2c {
+            pins: self.pins.clone(),
+        }))
+    }
+    fn spi(
+        &self,
+        _bus: u8,
+        _slave_select: u8,
+        _clock_speed: u32,
+    ) -> Result<Box<dyn SpiBus>, Error> {
+        Ok(Box::new(MockSpi {
+            pins: self.pins.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_level_changes_only() {
+        let hal = MockHal::new();
+        let mut out = hal.output(4, Level::Low).unwrap();
+        out.set_high();
+        out.set_high();
+        out.set_low();
+        let edges = hal.edges();
+        assert_eq!(edges.len(), 2);
+        assert_eq!((edges[0].pin, edges[0].level), (4, Level::High));
+        assert_eq!((edges[1].pin, edges[1].level), (4, Level::Low));
+        assert!(edges[0].at <= edges[1].at);
+        assert_eq!(hal.rising_edges(4), 1);
+
+        assert_eq!(hal.take_edges().len(), 2);
+        hal.set_recording(false);
+        out.set_high();
+        assert!(hal.edges().is_empty());
+        assert_eq!(hal.level(4), Some(Level::High));
+    }
+
+    #[test]
+    fn edge_listeners_drive_inputs() {
+        let hal = MockHal::new();
+        let mut out = hal.output(5, Level::Low).unwrap();
+        let input = hal.input(6, Pull::Down).unwrap();
+        hal.on_edge(|edge, pins| {
+            if edge.pin == 5 {
+                pins.set_input(6, edge.level);
+            }
+        });
+        assert!(input.is_low());
+        out.set_high();
+        assert!(input.is_high());
+        out.set_low();
+        assert!(input.is_low());
+    }
+
+    #[test]
+    fn unset_inputs_follow_their_pull() {
+        let hal = MockHal::new();
+        let up = hal.input(7, Pull::Up).unwrap();
+        let down = hal.input(8, Pull::Down).unwrap();
+        assert!(up.is_high());
+        assert!(down.is_low());
+        hal.set_input(7, Level::Low);
+        assert!(up.is_low());
+    }
+
+    #[test]
+    fn pins_are_claimed_until_dropped() {
+        let hal = MockHal::new();
+        let out = hal.output(9, Level::Low).unwrap();
+        assert!(matches!(hal.input(9, Pull::Up), Err(Error::PinUsed(9))));
+        assert!(matches!(hal.pwm(9), Err(Error::PinUsed(9))));
+        drop(out);
+        assert!(hal.input(9, Pull::Up).is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+pub mod mock;
+pub mod rpi;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Low,
+    High,
+}
+impl Level {
+    pub fn is_high(&self) -> bool {
+        *self == Level::High
+    }
+    pub fn is_low(&self) -> bool {
+        *self == Level::Low
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Gpio(rppal::gpio::Error),
+    I2c(rppal::i2c::Error),
+    Spi(rppal::spi::Error),
+    PinUsed(u8),
+    InvalidBus(u8),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Gpio(e) => write!(f, "gpio: {}", e),
+            Error::I2c(e) => write!(f, "i2c: {}", e),
+            Error::Spi(e) => write!(f, "spi: {}", e),
+            Error::PinUsed(pin) => write!(f, "pin {} is already in use", pin),
+            Error::InvalidBus(bus) => write!(f, "invalid bus {}", bus),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl From<rppal::gpio::Error> for Error {
+    fn from(e: rppal::gpio::Error) -> Self {
+        Error::Gpio(e)
+    }
+}
+impl From<rppal::i2c::Error> for Error {
+    fn from(e: rppal::i2c::Error) -> Self {
+        Error::I2c(e)
+    }
+}
+impl From<rppal::spi::Error> for Error {
+    fn from(e: rppal::spi::Error) -> Self {
+        Error::Spi(e)
+    }
+}
+
+pub trait OutputPin: Send {
+    fn write(&mut self, level: Level);
+    fn set_high(&mut self) {
+        self.write(Level::High)
+    }
+    fn set_low(&mut self) {
+        self.write(Level::Low)
+    }
+}
+
+pub trait InputPin: Send {
+    fn read(&self) -> Level;
+    fn is_high(&self) -> bool {
+        self.read().is_high()
+    }
+    fn is_low(&self) -> bool {
+        self.read().is_low()
+    }
+}
+
+/// Output pin with software pwm, duty cycle in 0.0..=1.0
+pub trait PwmPin: OutputPin {
+    fn set_pwm_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<(), Error>;
+    fn clear_pwm(&mut self) -> Result<(), Error>;
+}
+
+pub trait I2cBus: Send {
+    fn set_slave_address(&mut self, address: u16) -> Result<(), Error>;
+    fn block_write(&mut self, register: u8, block: &[u8]) -> Result<(), Error>;
+    fn block_read(&mut self, register: u8, block: &mut [u8]) -> Result<(), Error>;
+}
+
+pub trait SpiBus: Send {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error>;
+}
+
+/// Hands out pins and buses. `rpi::RppalHal` talks to the real hardware,
+/// `mock::MockHal` keeps everything in memory.
+pub trait Hal: Send + Sync {
+    fn output(&self, pin: u8, initial: Level) -> Result<Box<dyn OutputPin>, Error>;
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn InputPin>, Error>;
+    fn pwm(&self, pin: u8) -> Result<Box<dyn PwmPin>, Error>;
+    fn i2c(&self) -> Result<Box<dyn I2cBus>, Error>;
+    fn spi(&self, bus: u8, slave_select: u8, clock_speed: u32) -> Result<Box<dyn SpiBus>, Error>;
+}
@@ -0,0 +1,112 @@
+use rppal::{
+    gpio::Gpio,
+    i2c::I2c,
+    spi::{Bus, Mode, SlaveSelect, Spi},
+};
+
+use crate::hal::{Error, Hal, I2cBus, InputPin, Level, OutputPin, Pull, PwmPin, SpiBus};
+
+pub struct RppalHal {
+    gpio: Gpio,
+}
+impl RppalHal {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self { gpio: Gpio::new()? })
+    }
+}
+
+struct RppalOutput(rppal::gpio::OutputPin);
+struct RppalInput(rppal::gpio::InputPin);
+struct RppalI2c(I2c);
+struct RppalSpi(Spi);
+
+impl OutputPin for RppalOutput {
+    fn write(&mut self, level: Level) {
+        match level {
+            Level::Low => self.0.set_low(),
+            Level::High => self.0.set_high(),
+        }
+    }
+}
+impl PwmPin for RppalOutput {
+    fn set_pwm_frequency(&mut self, frequency: f64, duty_cycle: f64) -> Result<(), Error> {
+        Ok(self.0.set_pwm_frequency(frequency, duty_cycle)?)
+    }
+    fn clear_pwm(&mut self) -> Result<(), Error> {
+        Ok(self.0.clear_pwm()?)
+    }
+}
+impl InputPin for RppalInput {
+    fn read(&self) -> Level {
+        match self.0.is_high() {
+            true => Level::High,
+            false => Level::Low,
+        }
+    }
+}
+impl I2cBus for RppalI2c {
+    fn set_slave_address(&mut self, address: u16) -> Result<(), Error> {
+        Ok(self.0.set_slave_address(address)?)
+    }
+    fn block_write(&mut self, register: u8, block: &[u8]) -> Result<(), Error> {
+        Ok(self.0.block_write(register, block)?)
+    }
+    fn block_read(&mut self, register: u8, block: &mut [u8]) -> Result<(), Error> {
+        Ok(self.0.block_read(register, block)?)
+    }
+}
+impl SpiBus for RppalSpi {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        Ok(self.0.write(data)?)
+    }
+}
+
+impl Hal for RppalHal {
+    fn output(&self, pin: u8, initial: Level) -> Result<Box<dyn OutputPin>, Error> {
+        let pin = self.gpio.get(pin)?;
+        let pin = match initial {
+            Level::Low => pin.into_output_low(),
+            Level::High => pin.into_output_high(),
+        };
+        Ok(Box::new(RppalOutput(pin)))
+    }
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn InputPin>, Error> {
+        let pin = self.gpio.get(pin)?;
+        let pin = match pull {
+            Pull::None => pin.into_input(),
+            Pull::Up => pin.into_input_pullup(),
+            Pull::Down => pin.into_input_pulldown(),
+        };
+        Ok(Box::new(RppalInput(pin)))
+    }
+    fn pwm(&self, pin: u8) -> Result<Box<dyn PwmPin>, Error> {
+        Ok(Box::new(RppalOutput(self.gpio.get(pin)?.into_output_low())))
+    }
+    fn i2c(&self) -> Result<Box<dyn I2cBus>, Error> {
+        Ok(Box::new(RppalI2c(I2c::new()?)))
+    }
+    fn spi(&self, bus: u8, slave_select: u8, clock_speed: u32) -> Result<Box<dyn SpiBus>, Error> {
+        let bus = match bus {
+            0 => Bus::Spi0,
+            1 => Bus::Spi1,
+            2 => Bus::Spi2,
+            3 => Bus::Spi3,
+            4 => Bus::Spi4,
+            5 => Bus::Spi5,
+            6 => Bus::Spi6,
+            _ => return Err(Error::InvalidBus(bus)),
+        };
+        let slave_select = match slave_select {
+            0 => SlaveSelect::Ss0,
+            1 => SlaveSelect::Ss1,
+            2 => SlaveSelect::Ss2,
+            _ => return Err(Error::InvalidBus(slave_select)),
+        };
+        Ok(Box::new(RppalSpi(Spi::new(
+            bus,
+            slave_select,
+            clock_speed,
+            Mode::Mode0,
+        )?)))
+    }
+}
@@ -1,4 +1,5 @@
-#[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Default)]
 pub struct LED(pub u8, pub u8, pub u8);
 
 impl LED {
@@ -30,11 +31,6 @@ impl LED {
         )
     }
 }
-impl Default for LED {
-    fn default() -> Self {
-        Self(Default::default(), Default::default(), Default::default())
-    }
-}
 /// 0.0 = led1
 /// 1.0 = led2
 pub fn lerp_leds(led1: &LED, led2: &LED, t: f32) -> LED {
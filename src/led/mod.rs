@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 pub mod frame;
+#[allow(clippy::module_inception)]
 pub mod led;
 pub mod sequence;
 pub mod sequence_generator;
+pub mod sink;
 pub mod stripe;
@@ -113,7 +113,7 @@ impl Sequence {
 
         Self {
             frames: v,
-            framerate: framerate,
+            framerate,
         }
     }
     pub fn concat(&self, other: &Self) -> Self {
@@ -183,6 +183,7 @@ impl Div for Sequence {
 
 impl Shl<usize> for Sequence {
     type Output = Self;
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn shl(self, rhs: usize) -> Self::Output {
         let len = self.frames.len();
         let mut frs = self.frames;
@@ -195,6 +196,7 @@ impl Shl<usize> for Sequence {
 }
 impl Shr<usize> for Sequence {
     type Output = Self;
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn shr(self, rhs: usize) -> Self::Output {
         let len = self.frames.len();
         let mut frs = self.frames;
@@ -0,0 +1,93 @@
+use std::{
+    collections::VecDeque,
+    io::{Write, stdout},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ws2818_rgb_led_spi_driver::{adapter_gen::WS28xxAdapter, adapter_spi::WS28xxSpiAdapter};
+
+/// Where a `Stripe` sends its colors to
+pub trait StripeSink: Send {
+    fn write_rgb(&mut self, rgb: &[(u8, u8, u8)]) -> Result<(), String>;
+}
+
+pub struct SpiSink {
+    adapter: WS28xxSpiAdapter,
+}
+// the adapter keeps its spi device behind a Box<dyn HardwareDev> without Send
+unsafe impl Send for SpiSink {}
+impl SpiSink {
+    pub fn new(device: &str) -> Result<Self, String> {
+        Ok(Self {
+            adapter: WS28xxSpiAdapter::new(device)?,
+        })
+    }
+}
+impl StripeSink for SpiSink {
+    fn write_rgb(&mut self, rgb: &[(u8, u8, u8)]) -> Result<(), String> {
+        self.adapter.write_rgb(rgb)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    pub at: Duration,
+    pub leds: Vec<(u8, u8, u8)>,
+}
+
+/// Keeps the last `limit` frames with the time they were written
+#[derive(Clone)]
+pub struct RecordingSink {
+    start: Instant,
+    limit: usize,
+    frames: Arc<Mutex<VecDeque<RecordedFrame>>>,
+}
+impl RecordingSink {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            limit,
+            frames: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+    pub fn frames(&self) -> Vec<RecordedFrame> {
+        self.frames.lock().unwrap().iter().cloned().collect()
+    }
+    pub fn last(&self) -> Option<RecordedFrame> {
+        self.frames.lock().unwrap().back().cloned()
+    }
+    pub fn take(&self) -> Vec<RecordedFrame> {
+        self.frames.lock().unwrap().drain(..).collect()
+    }
+}
+impl StripeSink for RecordingSink {
+    fn write_rgb(&mut self, rgb: &[(u8, u8, u8)]) -> Result<(), String> {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() >= self.limit {
+            frames.pop_front();
+        }
+        frames.push_back(RecordedFrame {
+            at: self.start.elapsed(),
+            leds: rgb.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+/// Draws the stripe as one line of truecolor blocks, redrawn in place
+#[derive(Default)]
+pub struct TerminalSink;
+impl StripeSink for TerminalSink {
+    fn write_rgb(&mut self, rgb: &[(u8, u8, u8)]) -> Result<(), String> {
+        let mut line = String::with_capacity(rgb.len() * 20 + 8);
+        line.push('\r');
+        for (r, g, b) in rgb {
+            line.push_str(&format!("\x1b[38;2;{};{};{}m█", r, g, b));
+        }
+        line.push_str("\x1b[0m");
+        let mut out = stdout().lock();
+        out.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        out.flush().map_err(|e| e.to_string())
+    }
+}
@@ -10,18 +10,14 @@ use std::{
 
 use serde::Deserialize;
 use tokio::task::spawn_blocking;
-use ws2818_rgb_led_spi_driver::{
-    adapter_gen::WS28xxAdapter, adapter_spi::WS28xxSpiAdapter, encoding::encode_rgb,
-};
 
 use crate::led::{
     frame::Frame, led::LED, sequence::Sequence, sequence_generator::SequenzGenerator,
+    sink::StripeSink,
 };
 
-unsafe impl Send for Stripe {}
-unsafe impl Sync for Stripe {}
 pub struct Stripe {
-    adapter: WS28xxSpiAdapter,
+    sink: Box<dyn StripeSink>,
     number_of_leds: usize,
     running: Arc<AtomicBool>,
 }
@@ -52,12 +48,12 @@ impl PlayerColors {
     }
 }
 impl Stripe {
-    pub fn new(number_of_leds: usize) -> Self {
+    pub fn new(sink: Box<dyn StripeSink>, number_of_leds: usize) -> Self {
         assert!(number_of_leds > 0);
 
         let mut s = Self {
-            adapter: WS28xxSpiAdapter::new("/dev/spidev0.0").unwrap(),
-            number_of_leds: number_of_leds,
+            sink,
+            number_of_leds,
             running: Arc::new(AtomicBool::new(false)),
         };
         s.reset();
@@ -72,7 +68,7 @@ impl Stripe {
         for _ in 0..self.number_of_leds {
             v.push((0, 0, 0));
         }
-        self.adapter.write_rgb(&v).unwrap();
+        self.sink.write_rgb(&v).unwrap();
     }
     pub fn activate_sequenz(&mut self, sequence: Sequence) {
         self.reset();
@@ -81,9 +77,7 @@ impl Stripe {
         let rs = refine_sequence(&sequence);
 
         for frame in &rs {
-            self.adapter
-                .write_encoded_rgb(frame)
-                .expect("write encoded rgb");
+            self.sink.write_rgb(frame).expect("write rgb");
             sleep(wait);
             if !self.running.load(Ordering::SeqCst) {
                 break;
@@ -91,9 +85,7 @@ impl Stripe {
         }
         while self.running.load(Ordering::SeqCst) {
             for frame in &rs {
-                self.adapter
-                    .write_encoded_rgb(frame)
-                    .expect("write encoded rgb");
+                self.sink.write_rgb(frame).expect("write rgb");
                 sleep(wait);
                 if !self.running.load(Ordering::SeqCst) {
                     break;
@@ -106,7 +98,7 @@ impl Stripe {
         let end = (self.number_of_leds as f32 * fac) as usize;
         let mut v = Vec::new();
         for i in 0..self.number_of_leds {
-            if i + 1 <= end {
+            if i < end {
                 v.push(LED::from_color(color));
             } else {
                 v.push(LED::from_color((0, 0, 0)));
@@ -116,7 +108,7 @@ impl Stripe {
     }
     pub fn activate_frame(&mut self, frame: &Frame) {
         self.running.store(false, Ordering::SeqCst);
-        self.adapter
+        self.sink
             .write_rgb(&frame.to_vec())
             .expect("in activate frame");
     }
@@ -148,16 +140,11 @@ impl Stripe {
         SequenzGenerator::red_alert(self.number_of_leds)
     }
 }
-fn refine_sequence(seq: &Sequence) -> Vec<Vec<u8>> {
-    let mut v = Vec::new();
-    for frame in seq.get_frames() {
-        let mut w = Vec::new();
-        for led in &frame.0 {
-            w.extend_from_slice(&encode_rgb(led.0, led.1, led.2));
-        }
-        v.push(w);
-    }
-    v
+fn refine_sequence(seq: &Sequence) -> Vec<Vec<(u8, u8, u8)>> {
+    seq.get_frames()
+        .iter()
+        .map(|frame| frame.to_vec())
+        .collect()
 }
 pub fn start_stripe_controller(stripe: Stripe) -> Sender<Event> {
     let (tx, rx) = channel::<Event>();
@@ -171,13 +158,10 @@ pub fn start_stripe_controller(stripe: Stripe) -> Sender<Event> {
                 yy.store(false, Ordering::SeqCst);
                 let mut strp = xx.lock().unwrap();
                 strp.reset();
-                match event {
-                    Event::RedAlert => {
-                        let t = strp.red_alert();
-                        yy.store(true, Ordering::SeqCst);
-                        strp.activate_sequenz(t);
-                    }
-                    _ => {}
+                if let Event::RedAlert = event {
+                    let t = strp.red_alert();
+                    yy.store(true, Ordering::SeqCst);
+                    strp.activate_sequenz(t);
                 }
             });
         }
@@ -185,3 +169,57 @@ pub fn start_stripe_controller(stripe: Stripe) -> Sender<Event> {
 
     tx
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+    use crate::led::sink::RecordingSink;
+
+    const RED: (u8, u8, u8) = (255, 0, 0);
+    const OFF: (u8, u8, u8) = (0, 0, 0);
+
+    #[test]
+    fn frames_reach_the_sink() {
+        let sink = RecordingSink::new(10);
+        let mut stripe = Stripe::new(Box::new(sink.clone()), 8);
+        assert_eq!(sink.take()[0].leds, vec![OFF; 8]);
+
+        let half = stripe.strength(0.5, RED);
+        stripe.activate_frame(&half);
+        let frames = sink.take();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].leds, [vec![RED; 4], vec![OFF; 4]].concat());
+    }
+
+    #[test]
+    fn sequences_repeat_at_their_framerate_until_stopped() {
+        let sink = RecordingSink::new(100);
+        let mut stripe = Stripe::new(Box::new(sink.clone()), 4);
+        let running = stripe.get_running_clone();
+        let blink = stripe.create_blink(RED, 50.0);
+        sink.take();
+
+        running.store(true, Ordering::SeqCst);
+        let player = thread::spawn(move || stripe.activate_sequenz(blink));
+        let start = Instant::now();
+        while sink.frames().len() < 7 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+        running.store(false, Ordering::SeqCst);
+        player.join().unwrap();
+
+        let frames = sink.take();
+        // the reset, then on and off by turns
+        assert_eq!(frames[0].leds, vec![OFF; 4]);
+        for (i, frame) in frames[1..].iter().enumerate() {
+            let color = if i % 2 == 0 { RED } else { OFF };
+            assert_eq!(frame.leds, vec![color; 4], "frame {}", i + 1);
+        }
+        for pair in frames[1..].windows(2) {
+            assert!(pair[1].at - pair[0].at >= Duration::from_millis(20));
+        }
+    }
+}
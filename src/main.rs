@@ -1,5 +1,8 @@
+mod config;
 mod door;
+mod hal;
 mod led;
+mod sim;
 mod state;
 mod tasks;
 mod ws;
@@ -10,15 +13,30 @@ use std::{
     error::Error,
     sync::{Arc, Mutex},
 };
-use tokio::sync::broadcast;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::broadcast,
+};
 
 use crate::{
+    config::{ApproachWidth, Config, SafetyZone, StartupPolicy},
     door::{
+        audit::{AuditLog, Source},
+        controller::{DoorController, start_door_controller},
         detector::{Detector, Target},
-        door::{Door, start_door_controller},
+        door::Door,
+        monitor::DoorMonitor,
+        position::SavedPosition,
+        replay,
         routes::door_routes,
+        stats::StatsStore,
+    },
+    hal::rpi::RppalHal,
+    led::{
+        sink::{SpiSink, StripeSink, TerminalSink},
+        stripe::Stripe,
     },
-    led::stripe::Stripe,
+    sim::Simulation,
     state::AppState,
     tasks::updater::status_update,
     ws::{handler::ws_handler, static_files::static_handler},
@@ -26,41 +44,215 @@ use crate::{
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|a| a == "emulate-radar") {
+        return sim::radar::run_cli(&args[2..]);
+    }
+    if args.get(1).is_some_and(|a| a == "state-machine") {
+        return door::machine::run_cli(&args[2..]);
+    }
+
+    let replay_path = arg_value(&args, "--replay");
+    let replay_speed = match arg_value(&args, "--replay-speed") {
+        Some(s) => s
+            .parse::<f32>()
+            .ok()
+            .filter(|s| s.is_finite() && *s > 0.0)
+            .ok_or("--replay-speed must be a positive number")?,
+        None => 1.0,
+    };
+    // a replay never drives the real door
+    let simulate = args.iter().any(|a| a == "--simulate") || replay_path.is_some();
+    let config_path = arg_value(&args, "--config");
+    let mut config = match Config::load_or_default(
+        config_path.unwrap_or("config.toml"),
+        config_path.is_some(),
+    ) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let (ws_tx, _) = broadcast::channel(32);
 
-    let led_stripe = Arc::new(Mutex::new(Stripe::new(150)));
+    let (sink, d): (Box<dyn StripeSink>, _) = if simulate {
+        // the replay feeds the radar callbacks itself
+        let scenario = match replay_path {
+            Some(_) => None,
+            None => Some(arg_value(&args, "--scenario").unwrap_or("visit")),
+        };
+        match scenario {
+            Some(scenario) => println!("Simulation mode, radar plays {}", scenario),
+            None => println!("Simulation mode for the replay"),
+        }
+        sim::separate_files(&mut config.door);
+        let simulation = Simulation::start(&config, scenario)?;
+        // `--leds terminal` draws the stripe in the console instead of recording it
+        let sink: Box<dyn StripeSink> = match arg_value(&args, "--leds") {
+            None | Some("record") => Box::new(simulation.leds),
+            Some("terminal") => Box::new(TerminalSink),
+            Some(other) => {
+                return Err(format!("--leds {} is neither record nor terminal", other).into());
+            }
+        };
+        (sink, simulation.door.door)
+    } else {
+        let hal = RppalHal::new()?;
+        (
+            Box::new(SpiSink::new(&config.led.device)?),
+            Door::new(&hal, config.door.clone())?,
+        )
+    };
+
+    let led_stripe = Arc::new(Mutex::new(Stripe::new(sink, config.led.count)));
     let t_bool = led_stripe.lock().unwrap().get_running_clone();
 
-    let d = Door::new();
-    let tx_door = start_door_controller(d);
-    // let _ = tx_door.send(door::door::Event::Calibrate);
+    let door_monitor = d.lock().unwrap().get_monitor();
+    // the web UI follows moves through the status listeners below
+    d.lock()
+        .unwrap()
+        .add_motor_observer(Box::new(door_monitor.clone()));
+    let ws_tx_clone = ws_tx.clone();
+    door_monitor.subscribe(move |status| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorState(status.clone()));
+    });
+    let ws_tx_clone = ws_tx.clone();
+    door_monitor.subscribe_faults(move |fault| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorFault(fault.clone()));
+    });
+    let ws_tx_clone = ws_tx.clone();
+    door_monitor.subscribe_notices(move |notice| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorNotice(notice.clone()));
+    });
+    let audit = match AuditLog::open(&config.door.audit_log) {
+        Ok(audit) => audit,
+        Err(e) => {
+            println!(
+                "Cannot open {}: {}, audit log stays in memory",
+                config.door.audit_log, e
+            );
+            AuditLog::in_memory()
+        }
+    };
+    let ws_tx_clone = ws_tx.clone();
+    audit.subscribe(move |entry| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorAudit(entry.clone()));
+    });
+    let stats = match StatsStore::open(&config.door.stats_file, door_monitor.cm_per_step()) {
+        Ok(stats) => stats,
+        Err(e) => {
+            println!(
+                "Cannot open {}: {}, door stats start from zero",
+                config.door.stats_file, e
+            );
+            StatsStore::in_memory(door_monitor.cm_per_step())
+        }
+    };
+    let ws_tx_clone = ws_tx.clone();
+    stats.subscribe(move |stats| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorStats(stats.clone()));
+    });
+    let profiles = d.lock().unwrap().get_profiles();
+    let ws_tx_clone = ws_tx.clone();
+    profiles.subscribe(move |profile| {
+        let _ = ws_tx_clone.send(ws::messages::ServerMsg::DoorProfile(profile.clone()));
+    });
+    profiles.start_schedule(config.door.profile_schedule.clone());
+    let home = match config.door.startup {
+        StartupPolicy::Home => true,
+        StartupPolicy::Idle => false,
+        StartupPolicy::Restore => match SavedPosition::take(&config.door.position_file) {
+            Ok(Some(saved)) => {
+                d.lock().unwrap().restore(&saved);
+                false
+            }
+            Ok(None) => {
+                println!("No saved door position, homing");
+                true
+            }
+            Err(e) => {
+                println!(
+                    "Cannot restore {}: {}, homing",
+                    config.door.position_file, e
+                );
+                true
+            }
+        },
+    };
+    let tx_door = start_door_controller(d, audit.clone(), stats.clone());
+    if home {
+        tx_door.send(door::door::Event::Calibrate, Source::Startup);
+    }
 
-    for uart in [3, 5] {
-        #[allow(unused)]
+    let zone = config.radar.safety_zone;
+    let approach = config.radar.approach;
+    if let Some(path) = replay_path {
         let tx_clone = tx_door.clone();
         let ws_tx_clone = ws_tx.clone();
-        Detector::start(uart, move |arr: [Target; 3]| {
-            for t in &arr {
-                if t.is_alive() {
-                    if t.is_door_open() {
-                        let _ = tx_clone.send(door::door::Event::Open);
-                    } else if t.is_close_door() {
-                        let _ = tx_clone.send(door::door::Event::Close);
-                    }
-                }
-            }
-            let _ = ws_tx_clone.send(ws::messages::ServerMsg::Targets {
-                id: uart,
-                targets: arr,
-            });
-        });
+        let monitor = door_monitor.clone();
+        let entry_uarts = config.radar.entry_uarts.clone();
+        replay::start(
+            path,
+            replay_speed,
+            config.radar.thresholds,
+            move |uart, arr| {
+                let radar = Radar {
+                    uart,
+                    entry: entry_uarts.contains(&uart),
+                };
+                on_targets(
+                    radar,
+                    arr,
+                    &zone,
+                    &approach,
+                    &monitor,
+                    &tx_clone,
+                    &ws_tx_clone,
+                )
+            },
+        )?;
+    } else {
+        for &uart in &config.radar.uarts {
+            let tx_clone = tx_door.clone();
+            let ws_tx_clone = ws_tx.clone();
+            let monitor = door_monitor.clone();
+            let radar = Radar {
+                uart,
+                entry: config.radar.entry_uarts.contains(&uart),
+            };
+            // emulated frames stay out of the recording
+            let record = !simulate;
+            Detector::start(
+                uart,
+                config.radar.thresholds,
+                record,
+                move |arr: [Target; 3]| {
+                    on_targets(
+                        radar,
+                        arr,
+                        &zone,
+                        &approach,
+                        &monitor,
+                        &tx_clone,
+                        &ws_tx_clone,
+                    )
+                },
+            );
+        }
     }
 
     let state = Arc::new(AppState {
-        led_stripe: led_stripe,
+        led_stripe,
         led_repeat: t_bool,
 
         door: tx_door,
+        door_monitor: door_monitor.clone(),
+        audit,
+        stats,
+        profiles,
+        entry_radars: !config.radar.entry_uarts.is_empty(),
 
         tx: ws_tx,
     });
@@ -73,10 +265,80 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .fallback(get(static_handler))
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:14444")
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", config.network.port))
+        .await
+        .unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
-    axum::serve(listener, app).await.unwrap();
 
+    match SavedPosition::from_status(&door_monitor.status()) {
+        Some(saved) => match saved.save(&config.door.position_file) {
+            Ok(()) => println!(
+                "Saved door position {:?} at step {}",
+                saved.state, saved.steps
+            ),
+            Err(e) => println!("Cannot save {}: {}", config.door.position_file, e),
+        },
+        None => println!("Door position unknown at shutdown, not saved"),
+    }
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Radar {
+    uart: u8,
+    /// listed in `radar.entry_uarts`
+    entry: bool,
+}
+
+fn on_targets(
+    radar: Radar,
+    arr: [Target; 3],
+    zone: &SafetyZone,
+    approach: &ApproachWidth,
+    monitor: &DoorMonitor,
+    tx_door: &DoorController,
+    ws_tx: &broadcast::Sender<ws::messages::ServerMsg>,
+) {
+    let Radar { uart, entry } = radar;
+    let source = Source::Radar { uart };
+    // the safety zone keeps working while automation is off
+    let automated = monitor.automation().radar_allowed(entry);
+    // only an open or closing door reacts, every other state would reject
+    // it on each frame someone stands in the zone
+    let guarding = monitor.state().accepts(&door::door::Event::Obstruction);
+    for t in &arr {
+        if guarding && t.is_present() && zone.contains(t.get_points()[0]) {
+            tx_door.send(door::door::Event::Obstruction, source.clone());
+        }
+        if automated && t.is_alive() {
+            if t.is_door_open() {
+                let width_cm = approach.width_for(t.approach_speed());
+                tx_door.send(door::door::Event::Open { width_cm }, source.clone());
+            } else if t.is_close_door() {
+                tx_door.send(door::door::Event::Close, source.clone());
+            }
+        }
+    }
+    let _ = ws_tx.send(ws::messages::ServerMsg::Targets {
+        id: uart,
+        targets: arr,
+    });
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
@@ -0,0 +1,155 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    config::DoorConfig,
+    hal::{
+        Level,
+        mock::{Edge, MockHal, MockPins},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct Endstop {
+    pub pin: u8,
+    /// first step where the switch is released again when moving positive
+    pub release_at: i64,
+    /// how many steps before `release_at` the switch is pressed
+    pub width: i64,
+}
+impl Endstop {
+    fn is_pressed(&self, position: i64) -> bool {
+        position >= self.release_at - self.width && position < self.release_at
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlantConfig {
+    pub dir_pin: u8,
+    pub step_pin: u8,
+    pub endstops: Vec<Endstop>,
+    /// mechanical end of the rail, steps beyond it get lost
+    pub travel: (i64, i64),
+    pub start_position: i64,
+}
+impl Default for PlantConfig {
+    fn default() -> Self {
+        Self::from_door(&DoorConfig::default())
+    }
+}
+impl PlantConfig {
+    /// Plant matching the pins and endstop positions of a door config
+    pub fn from_door(door: &DoorConfig) -> Self {
+        let e = &door.endstops;
+        Self {
+            dir_pin: door.stepper.dir_pin,
+            step_pin: door.stepper.step_pin,
+            endstops: vec![
+                Endstop {
+                    pin: e.close_pin,
+                    release_at: e.close_step,
+                    width: 400,
+                },
+                Endstop {
+                    pin: e.middle_pin,
+                    release_at: e.middle_step,
+                    width: 120,
+                },
+                Endstop {
+                    pin: e.furtherest_pin,
+                    release_at: e.furtherest_step,
+                    width: 120,
+                },
+            ],
+            travel: (-250, e.furtherest_step + 500),
+            start_position: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PlantState {
+    position: i64,
+    dir_positive: bool,
+    steps: u64,
+    lost_steps: u64,
+}
+
+/// Sliding door model behind a `MockHal`: step pulses move the carriage,
+/// the endstop inputs follow the carriage position.
+#[derive(Clone)]
+pub struct DoorPlant {
+    config: Arc<PlantConfig>,
+    state: Arc<Mutex<PlantState>>,
+}
+impl DoorPlant {
+    pub fn attach(hal: &MockHal, config: PlantConfig) -> Self {
+        let plant = Self {
+            config: Arc::new(config),
+            state: Arc::new(Mutex::new(PlantState {
+                position: 0,
+                dir_positive: false,
+                steps: 0,
+                lost_steps: 0,
+            })),
+        };
+        plant.state.lock().unwrap().position = plant.config.start_position;
+        for e in &plant.config.endstops {
+            hal.set_input(e.pin, endstop_level(e, plant.config.start_position));
+        }
+
+        let p = plant.clone();
+        hal.on_edge(move |edge, pins| p.on_edge(edge, pins));
+        plant
+    }
+    fn on_edge(&self, edge: &Edge, pins: &mut MockPins) {
+        let mut s = self.state.lock().unwrap();
+        if edge.pin == self.config.dir_pin {
+            s.dir_positive = edge.level == Level::High;
+        } else if edge.pin == self.config.step_pin && edge.level == Level::High {
+            s.steps += 1;
+            let next = s.position + if s.dir_positive { 1 } else { -1 };
+            let (min, max) = self.config.travel;
+            if next < min || next > max {
+                s.lost_steps += 1;
+                return;
+            }
+            s.position = next;
+            for e in &self.config.endstops {
+                pins.set_input(e.pin, endstop_level(e, next));
+            }
+        }
+    }
+    #[cfg(test)]
+    pub fn position(&self) -> i64 {
+        self.state.lock().unwrap().position
+    }
+    /// total step pulses seen, including lost ones
+    #[cfg(test)]
+    pub fn steps(&self) -> u64 {
+        self.state.lock().unwrap().steps
+    }
+    #[cfg(test)]
+    pub fn lost_steps(&self) -> u64 {
+        self.state.lock().unwrap().lost_steps
+    }
+    /// moves the carriage without step pulses, e.g. a slipping belt
+    #[cfg(test)]
+    pub fn slip(&self, hal: &MockHal, steps: i64) {
+        let position = {
+            let mut s = self.state.lock().unwrap();
+            s.position += steps;
+            s.position
+        };
+        for e in &self.config.endstops {
+            hal.set_input(e.pin, endstop_level(e, position));
+        }
+    }
+}
+
+fn endstop_level(endstop: &Endstop, position: i64) -> Level {
+    // switches pull the input low while pressed
+    match endstop.is_pressed(position) {
+        true => Level::Low,
+        false => Level::High,
+    }
+}
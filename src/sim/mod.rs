@@ -0,0 +1,361 @@
+pub mod door_plant;
+pub mod radar;
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    config::{Config, DoorConfig},
+    door::door::Door,
+    hal::{Error, mock::MockHal},
+    led::sink::RecordingSink,
+    sim::{
+        door_plant::{DoorPlant, PlantConfig},
+        radar::RadarScript,
+    },
+};
+
+/// A `Door` wired to a `DoorPlant` instead of the belt drive
+pub struct SimulatedDoor {
+    /// hal and plant are for tests to look at and disturb the carriage
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub hal: MockHal,
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub plant: DoorPlant,
+    pub door: Arc<Mutex<Door>>,
+}
+impl SimulatedDoor {
+    pub fn new(config: DoorConfig, plant: PlantConfig) -> Result<Self, Error> {
+        let hal = MockHal::new();
+        // the plant already tracks the carriage, a full move would record ~16k edges
+        hal.set_recording(false);
+        let plant = DoorPlant::attach(&hal, plant);
+        let door = Door::new(&hal, config)?;
+        Ok(Self { hal, plant, door })
+    }
+}
+
+/// Points the door files at `sim_` copies next to them, so a simulation
+/// never leaves fake wear or a fake position for the real door to restore
+pub fn separate_files(config: &mut DoorConfig) {
+    for file in [
+        &mut config.calibration_file,
+        &mut config.audit_log,
+        &mut config.stats_file,
+        &mut config.position_file,
+    ] {
+        let path = Path::new(file.as_str());
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        *file = path
+            .with_file_name(format!("sim_{}", name))
+            .display()
+            .to_string();
+    }
+}
+
+/// Everything `main` needs to run without the Pi: the door on a plant,
+/// recorded LED frames and emulated radars on the configured UARTs
+pub struct Simulation {
+    pub door: SimulatedDoor,
+    pub leds: RecordingSink,
+}
+impl Simulation {
+    /// Without a scenario no radars are emulated
+    pub fn start(
+        config: &Config,
+        scenario: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(scenario) = scenario {
+            let script = RadarScript::scenario(scenario)
+                .ok_or_else(|| format!("unknown radar scenario {}", scenario))?;
+            // the first radar plays the scenario, the others stay empty
+            for (i, &uart) in config.radar.uarts.iter().enumerate() {
+                match i {
+                    0 => radar::serve(uart, script.clone(), true)?,
+                    _ => radar::serve(uart, RadarScript::default(), false)?,
+                };
+            }
+        }
+        Ok(Self {
+            door: SimulatedDoor::new(config.door.clone(), PlantConfig::from_door(&config.door))?,
+            leds: RecordingSink::new(1000),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::channel,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::door::{
+        audit::{AuditLog, AuditQuery, Source},
+        controller::{DoorController, start_door_controller},
+        detector::{Target, parse_frame},
+        door::{AutomationMode, Event, State},
+        machine::Reply,
+        monitor::{DoorFault, DoorMonitor},
+        position::SavedPosition,
+        stats::StatsStore,
+    };
+    use crate::{
+        config::{ApproachWidth, SafetyZone, Thresholds},
+        hal::Level,
+        sim::radar::{RadarTarget, encode_frame},
+    };
+
+    fn config() -> DoorConfig {
+        let mut config = DoorConfig::default();
+        // homing at the default speed takes longer than a test should
+        config.stepper.motion.calibration_speed_cm_s = 20.0;
+        config.calibration_file = std::env::temp_dir()
+            .join("first-rpi-test-no-calibration.json")
+            .display()
+            .to_string();
+        config
+    }
+
+    fn simulated() -> SimulatedDoor {
+        let config = config();
+        let plant = PlantConfig::from_door(&config);
+        SimulatedDoor::new(config, plant).unwrap()
+    }
+
+    fn controlled(sim: &SimulatedDoor) -> (DoorController, DoorMonitor, AuditLog) {
+        let monitor = sim.door.lock().unwrap().get_monitor();
+        let audit = AuditLog::in_memory();
+        let stats = StatsStore::in_memory(monitor.cm_per_step());
+        let controller = start_door_controller(sim.door.clone(), audit.clone(), stats);
+        (controller, monitor, audit)
+    }
+
+    fn wait_for(monitor: &DoorMonitor, state: State) {
+        let start = Instant::now();
+        while monitor.state() != state {
+            assert!(
+                start.elapsed() < Duration::from_secs(20),
+                "door stuck in {:?} waiting for {:?}",
+                monitor.state(),
+                state
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn calibrates_opens_and_closes() {
+        let sim = simulated();
+        let (controller, monitor, _) = controlled(&sim);
+        let open = sim.door.lock().unwrap().open_position();
+
+        controller.send(Event::Calibrate, Source::Ws);
+        wait_for(&monitor, State::Closed);
+        assert_eq!(sim.plant.position(), 0);
+
+        controller.send(Event::Open { width_cm: None }, Source::Ws);
+        wait_for(&monitor, State::Opened);
+        assert_eq!(sim.plant.position(), open);
+        assert_eq!(monitor.status().steps, open);
+
+        controller.send(Event::Close, Source::Ws);
+        wait_for(&monitor, State::Closed);
+        assert_eq!(sim.plant.position(), 0);
+        assert_eq!(sim.plant.lost_steps(), 0);
+        assert_eq!(sim.plant.steps(), sim.door.lock().unwrap().get_travel());
+    }
+
+    #[test]
+    fn close_turns_an_opening_door_around() {
+        let sim = simulated();
+        let (controller, monitor, audit) = controlled(&sim);
+        let open = sim.door.lock().unwrap().open_position();
+        controller.send(Event::Calibrate, Source::Ws);
+        wait_for(&monitor, State::Closed);
+
+        controller.send(Event::Open { width_cm: None }, Source::Ws);
+        while sim.plant.position() < open / 4 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let reply = controller.command(Event::Close, Source::Ws);
+        assert_eq!(
+            reply.blocking_recv().unwrap(),
+            Reply::Accepted(State::Closing)
+        );
+        wait_for(&monitor, State::Closed);
+
+        assert_eq!(sim.plant.position(), 0);
+        assert_eq!(monitor.status().steps, 0);
+        let ends: Vec<Event> = audit
+            .query(&AuditQuery::default())
+            .into_iter()
+            .filter(|e| e.acted)
+            .map(|e| e.event)
+            .collect();
+        assert!(
+            !ends.contains(&Event::IsOpen),
+            "door reached open: {:?}",
+            ends
+        );
+    }
+
+    #[test]
+    fn middle_endstop_corrects_a_slipped_belt() {
+        let sim = simulated();
+        let mut door = sim.door.lock().unwrap();
+        let (tx, rx) = channel();
+        door.get_monitor()
+            .subscribe_faults(move |fault| tx.send(fault.clone()).unwrap());
+        assert!(door.calibrate());
+        door.move_to(0);
+        assert_eq!(sim.plant.position(), 0);
+
+        // above `drift_threshold_steps`, so it is also reported
+        sim.plant.slip(&sim.hal, 30);
+        let open = door.open_position();
+        assert_eq!(door.move_to(open), open);
+
+        assert_eq!(sim.plant.position(), open);
+        assert_eq!(door.get_drift_corrections(), 1);
+        let middle = door.get_config().endstops.middle_step;
+        match rx.try_recv().unwrap() {
+            DoorFault::Drift { expected, measured } => {
+                assert_eq!((expected, measured), (middle, middle - 30))
+            }
+        }
+    }
+
+    #[test]
+    fn held_stop_button_refuses_reset() {
+        let mut config = config();
+        config.emergency_stop_pin = Some(26);
+        let sim = SimulatedDoor::new(config.clone(), PlantConfig::from_door(&config)).unwrap();
+        let (controller, monitor, _) = controlled(&sim);
+        let reset = || {
+            controller
+                .command(Event::Reset, Source::Ws)
+                .blocking_recv()
+                .unwrap()
+        };
+
+        sim.hal.set_input(26, Level::Low);
+        wait_for(&monitor, State::EmergencyStop);
+        assert_eq!(reset(), Reply::Rejected(State::EmergencyStop));
+        assert_eq!(monitor.state(), State::EmergencyStop);
+
+        // the button thread polls every 5 ms
+        sim.hal.set_input(26, Level::High);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(reset(), Reply::Completed(State::Undefined));
+    }
+
+    #[test]
+    fn simulation_files_are_separate() {
+        let mut config = DoorConfig {
+            stats_file: "/var/lib/door/stats.json".to_string(),
+            ..DoorConfig::default()
+        };
+        separate_files(&mut config);
+        assert_eq!(config.calibration_file, "sim_door_calibration.json");
+        assert_eq!(config.audit_log, "sim_door_audit.jsonl");
+        assert_eq!(config.stats_file, "/var/lib/door/sim_stats.json");
+        assert_eq!(config.position_file, "sim_door_position.json");
+    }
+
+    #[test]
+    fn safety_zone_stops_a_closing_door_and_reopens() {
+        let sim = simulated();
+        let (controller, monitor, _) = controlled(&sim);
+        // the safety zone works without the radar heuristics
+        monitor.set_automation(AutomationMode::Manual);
+        controller.send(Event::Calibrate, Source::Ws);
+        wait_for(&monitor, State::Closed);
+        controller.send(Event::Open { width_cm: None }, Source::Ws);
+        wait_for(&monitor, State::Opened);
+        let open = sim.plant.position();
+
+        controller.send(Event::Close, Source::Ws);
+        // cruising by the middle of the way
+        while sim.plant.position() > open / 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // someone steps into the doorway, as the emulated radar reports it
+        let person = RadarTarget {
+            x: 0,
+            y: 400,
+            speed: 0,
+            resolution: 360,
+        };
+        let frame = encode_frame(&[person, RadarTarget::default(), RadarTarget::default()]);
+        let mut targets: [Target; 3] = Default::default();
+        for (target, (x, y, speed, res)) in targets.iter_mut().zip(parse_frame(&frame).unwrap()) {
+            *target = Target::new(Thresholds::default());
+            target.update(x, y, speed, res);
+        }
+        let (ws_tx, _) = broadcast::channel(8);
+        let seen_at = sim.plant.position();
+        crate::on_targets(
+            crate::Radar {
+                uart: 3,
+                entry: false,
+            },
+            targets,
+            &SafetyZone::default(),
+            &ApproachWidth::default(),
+            &monitor,
+            &controller,
+            &ws_tx,
+        );
+
+        let mut closest = seen_at;
+        let start = Instant::now();
+        while monitor.state() != State::Opened {
+            assert!(start.elapsed() < Duration::from_secs(20), "never reopened");
+            closest = closest.min(sim.plant.position());
+            thread::sleep(Duration::from_millis(1));
+        }
+        // about 290 steps of hard braking from 2900 steps of ramp
+        assert!(seen_at - closest < 600, "{} steps on", seen_at - closest);
+        assert_eq!(sim.plant.position(), open);
+    }
+
+    #[test]
+    fn motion_waits_for_a_known_position() {
+        let path =
+            std::env::temp_dir().join(format!("door-restore-test-{}.json", std::process::id()));
+        std::fs::write(&path, "not json").unwrap();
+        let open = Event::Open { width_cm: None };
+
+        // a failed restore leaves the door undefined until it homed
+        assert!(SavedPosition::take(&path).is_err());
+        let sim = simulated();
+        let (controller, monitor, _) = controlled(&sim);
+        for event in [open.clone(), Event::Close, Event::Lock] {
+            let reply = controller.command(event, Source::Ws).blocking_recv();
+            assert_eq!(reply.unwrap(), Reply::Rejected(State::Undefined));
+        }
+        assert_eq!(sim.plant.steps(), 0);
+
+        // a restored one moves right away
+        let mut status = monitor.status();
+        status.state = State::Closed;
+        SavedPosition::from_status(&status)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let sim = simulated();
+        let saved = SavedPosition::take(&path).unwrap().unwrap();
+        sim.door.lock().unwrap().restore(&saved);
+        let (controller, monitor, _) = controlled(&sim);
+        let reply = controller.command(open, Source::Ws).blocking_recv();
+        assert_eq!(reply.unwrap(), Reply::Accepted(State::Opening));
+        wait_for(&monitor, State::Opened);
+    }
+}
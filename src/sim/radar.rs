@@ -0,0 +1,226 @@
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::net::UnixListener,
+    path::Path,
+    thread::{JoinHandle, sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::door::detector::{encode_ld2450_value, socket_path};
+
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+const RESOLUTION: u16 = 360;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RadarTarget {
+    pub x: i16,
+    pub y: i16,
+    pub speed: i16,
+    pub resolution: u16,
+}
+
+/// Point of a target path, `t` in seconds, `x`/`y` in mm like the radar reports them
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Waypoint {
+    pub t: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Up to three target paths, a target only exists between its first and last waypoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RadarScript {
+    pub tracks: Vec<Vec<Waypoint>>,
+}
+impl RadarScript {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let script: RadarScript = serde_json::from_str(&text)?;
+        if script.tracks.len() > 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the LD2450 reports at most 3 targets",
+            ));
+        }
+        Ok(script)
+    }
+    pub fn scenario(name: &str) -> Option<Self> {
+        let w = |t, x, y| Waypoint { t, x, y };
+        let tracks = match name {
+            "approach" => vec![vec![w(0.0, 300.0, 3500.0), w(4.0, 0.0, 450.0)]],
+            "walk-past" => vec![vec![w(0.0, -2500.0, 1400.0), w(4.0, 2500.0, 1400.0)]],
+            "doorway" => vec![vec![
+                w(0.0, 0.0, 400.0),
+                w(3.0, 40.0, 420.0),
+                w(6.0, -30.0, 390.0),
+                w(10.0, 0.0, 400.0),
+            ]],
+            // leaves the doorway sideways and steps back in just as the
+            // door closes after the default cooldown, meant to run with --repeat
+            "step-back" => vec![vec![
+                w(0.0, 0.0, 800.0),
+                w(3.0, 1500.0, 800.0),
+                w(4.4, 1500.0, 800.0),
+                w(7.4, 0.0, 800.0),
+            ]],
+            "leave" => vec![vec![w(0.0, 0.0, 400.0), w(4.0, 200.0, 3500.0)]],
+            "visit" => vec![vec![
+                w(0.0, 300.0, 3500.0),
+                w(4.0, 0.0, 450.0),
+                w(9.0, 0.0, 420.0),
+                w(13.0, 200.0, 3500.0),
+            ]],
+            _ => return None,
+        };
+        Some(Self { tracks })
+    }
+    pub fn duration(&self) -> Duration {
+        let end = self
+            .tracks
+            .iter()
+            .filter_map(|track| track.last())
+            .map(|w| w.t)
+            .fold(0.0, f32::max);
+        Duration::from_secs_f32(end)
+    }
+    pub fn sample(&self, at: Duration) -> [RadarTarget; 3] {
+        let mut targets = [RadarTarget::default(); 3];
+        for (target, track) in targets.iter_mut().zip(&self.tracks) {
+            if let Some(t) = sample_track(track, at.as_secs_f32()) {
+                *target = t;
+            }
+        }
+        targets
+    }
+}
+
+fn sample_track(track: &[Waypoint], t: f32) -> Option<RadarTarget> {
+    let (a, b) = track
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+        .find(|(a, b)| a.t <= t && t <= b.t)?;
+    let span = (b.t - a.t).max(f32::EPSILON);
+    let f = (t - a.t) / span;
+    let x = a.x + (b.x - a.x) * f;
+    let y = a.y + (b.y - a.y) * f;
+    // radial speed in cm/s, negative while coming closer
+    let dist_a = a.x.hypot(a.y);
+    let dist_b = b.x.hypot(b.y);
+    let speed = (dist_b - dist_a) / span / 10.0;
+    Some(RadarTarget {
+        x: x as i16,
+        y: y as i16,
+        speed: speed as i16,
+        resolution: RESOLUTION,
+    })
+}
+
+pub fn encode_frame(targets: &[RadarTarget; 3]) -> [u8; 30] {
+    let mut frame = [0u8; 30];
+    frame[..4].copy_from_slice(&[0xAA, 0xFF, 0x03, 0x00]);
+    for (i, t) in targets.iter().enumerate() {
+        let offset = 4 + (i * 8);
+        if (t.x, t.y) == (0, 0) {
+            continue;
+        }
+        let (xl, xh) = encode_ld2450_value(t.x);
+        let (yl, yh) = encode_ld2450_value(t.y);
+        let (sl, sh) = encode_ld2450_value(t.speed);
+        frame[offset..offset + 8].copy_from_slice(&[
+            xl,
+            xh,
+            yl,
+            yh,
+            sl,
+            sh,
+            (t.resolution & 0xFF) as u8,
+            (t.resolution >> 8) as u8,
+        ]);
+    }
+    frame[28] = 0x55;
+    frame[29] = 0xCC;
+    frame
+}
+
+/// Listens on the detector socket of `uart_num` and plays `script` to every client.
+/// After the script the radar keeps reporting empty frames unless `repeat` is set.
+pub fn serve(uart_num: u8, script: RadarScript, repeat: bool) -> io::Result<JoinHandle<()>> {
+    let path = socket_path(uart_num);
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    Ok(spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            println!("Radar {} emulator: client connected", uart_num);
+            let mut start = Instant::now();
+            loop {
+                let mut elapsed = start.elapsed();
+                if repeat && elapsed > script.duration() {
+                    start = Instant::now();
+                    elapsed = Duration::ZERO;
+                }
+                let frame = encode_frame(&script.sample(elapsed));
+                if stream.write_all(&frame).is_err() {
+                    println!("Radar {} emulator: client gone", uart_num);
+                    break;
+                }
+                sleep(FRAME_INTERVAL);
+            }
+        }
+    }))
+}
+
+/// `emulate-radar <uart> <scenario|script.json> [--repeat]`
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: emulate-radar <uart> <approach|walk-past|doorway|step-back|leave|visit|script.json> [--repeat]";
+    let (Some(uart), Some(source)) = (args.first(), args.get(1)) else {
+        return Err(usage.into());
+    };
+    let uart: u8 = uart.parse().map_err(|_| usage)?;
+    let script = match RadarScript::scenario(source) {
+        Some(script) => script,
+        None => RadarScript::from_file(source)?,
+    };
+    let repeat = args.iter().any(|a| a == "--repeat");
+    println!("Radar {} emulator on {}", uart, socket_path(uart));
+    let _ = serve(uart, script, repeat)?.join();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::door::detector::parse_frame;
+
+    #[test]
+    fn frames_parse_back() {
+        let targets = [
+            RadarTarget {
+                x: -820,
+                y: 1450,
+                speed: -16,
+                resolution: RESOLUTION,
+            },
+            RadarTarget {
+                x: 0,
+                y: 300,
+                speed: 0,
+                resolution: 320,
+            },
+            RadarTarget {
+                x: 2500,
+                y: 6000,
+                speed: 35,
+                resolution: 360,
+            },
+        ];
+        let parsed = parse_frame(&encode_frame(&targets)).unwrap();
+        for (t, raw) in targets.iter().zip(parsed) {
+            assert_eq!(raw, (t.x, t.y, t.speed, t.resolution));
+        }
+    }
+}
@@ -1,14 +1,27 @@
-use std::sync::{Arc, Mutex, atomic::AtomicBool, mpsc::Sender};
+use std::sync::{Arc, Mutex, atomic::AtomicBool};
 
 use tokio::sync::broadcast;
 
-use crate::{door, led::stripe::Stripe, ws::messages::ServerMsg};
+use crate::{
+    door::{
+        audit::AuditLog, controller::DoorController, monitor::DoorMonitor,
+        profile::MotionProfiles, stats::StatsStore,
+    },
+    led::stripe::Stripe,
+    ws::messages::ServerMsg,
+};
 
 pub struct AppState {
     pub led_repeat: Arc<AtomicBool>,
     pub led_stripe: Arc<Mutex<Stripe>>,
     // pub led_tx: Sender<led::stripe::Event>,
-    pub door: Sender<door::door::Event>,
+    pub door: DoorController,
+    pub door_monitor: DoorMonitor,
+    pub audit: AuditLog,
+    pub stats: StatsStore,
+    pub profiles: MotionProfiles,
+    /// `radar.entry_uarts` is not empty, the entry automation needs one
+    pub entry_radars: bool,
 
     pub tx: broadcast::Sender<ServerMsg>,
 }
@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use rppal::gpio::{Error, Gpio};
+use crate::hal::{Error, Hal, Level, Pull};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -14,9 +14,9 @@ pub struct Hcsr04 {
 }
 
 impl Hcsr04 {
-    pub fn new(trig_pin: u8, echo_pin: u8) -> Result<Self, Error> {
-        let mut trig = Gpio::new()?.get(trig_pin)?.into_output_low();
-        let echo = Gpio::new()?.get(echo_pin)?.into_input_pulldown();
+    pub fn new(hal: &dyn Hal, trig_pin: u8, echo_pin: u8) -> Result<Self, Error> {
+        let mut trig = hal.output(trig_pin, Level::Low)?;
+        let echo = hal.input(echo_pin, Pull::Down)?;
 
         let distance = Arc::new(Mutex::new(0.0));
         let distance_clone = distance.clone();
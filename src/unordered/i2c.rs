@@ -2,7 +2,7 @@
 
 use std::sync::{Arc, Mutex};
 
-use rppal::i2c::{Error, I2c};
+use crate::hal::{Error, Hal, I2cBus};
 
 fn bcd2dec(bcd: u8) -> u8 {
     (((bcd & 0xF0) >> 4) * 10) + (bcd & 0x0F)
@@ -17,12 +17,12 @@ trait I2CDevice {
 }
 
 pub struct I2CMaster {
-    i2c: I2c,
+    i2c: Box<dyn I2cBus>,
 }
 
 impl I2CMaster {
-    pub fn new() -> Result<Self, Error> {
-        Ok(Self { i2c: I2c::new()? })
+    pub fn new(hal: &dyn Hal) -> Result<Self, Error> {
+        Ok(Self { i2c: hal.i2c()? })
     }
 
     pub fn send(&mut self, slave_address: u16, reg: u8, block: &[u8]) -> Result<(), Error> {
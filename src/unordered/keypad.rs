@@ -1,21 +1,23 @@
 #![allow(dead_code)]
 
-use rppal::gpio::{Error, Gpio, InputPin, OutputPin};
+use crate::hal::{Error, Hal, InputPin, Level, OutputPin, Pull};
 
 pub struct Keypad {
-    in1: InputPin,
-    in2: InputPin,
-    in3: InputPin,
-    in4: InputPin,
-    out1: OutputPin,
-    out2: OutputPin,
-    out3: OutputPin,
-    out4: OutputPin,
+    in1: Box<dyn InputPin>,
+    in2: Box<dyn InputPin>,
+    in3: Box<dyn InputPin>,
+    in4: Box<dyn InputPin>,
+    out1: Box<dyn OutputPin>,
+    out2: Box<dyn OutputPin>,
+    out3: Box<dyn OutputPin>,
+    out4: Box<dyn OutputPin>,
     pub state: [[bool; 4]; 4],
 }
 
 impl Keypad {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        hal: &dyn Hal,
         i1: u8,
         i2: u8,
         i3: u8,
@@ -26,14 +28,14 @@ impl Keypad {
         o4: u8,
     ) -> Result<Self, Error> {
         Ok(Keypad {
-            in1: Gpio::new()?.get(i1)?.into_input_pulldown(),
-            in2: Gpio::new()?.get(i2)?.into_input_pulldown(),
-            in3: Gpio::new()?.get(i3)?.into_input_pulldown(),
-            in4: Gpio::new()?.get(i4)?.into_input_pulldown(),
-            out1: Gpio::new()?.get(o1)?.into_output_low(),
-            out2: Gpio::new()?.get(o2)?.into_output_low(),
-            out3: Gpio::new()?.get(o3)?.into_output_low(),
-            out4: Gpio::new()?.get(o4)?.into_output_low(),
+            in1: hal.input(i1, Pull::Down)?,
+            in2: hal.input(i2, Pull::Down)?,
+            in3: hal.input(i3, Pull::Down)?,
+            in4: hal.input(i4, Pull::Down)?,
+            out1: hal.output(o1, Level::Low)?,
+            out2: hal.output(o2, Level::Low)?,
+            out3: hal.output(o3, Level::Low)?,
+            out4: hal.output(o4, Level::Low)?,
             state: [
                 [false, false, false, false],
                 [false, false, false, false],
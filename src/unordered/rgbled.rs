@@ -1,18 +1,18 @@
 #![allow(dead_code)]
-use rppal::gpio::{Error, Gpio, OutputPin};
+use crate::hal::{Error, Hal, PwmPin};
 
 pub struct RGBLed {
-    r_pin: OutputPin,
-    g_pin: OutputPin,
-    b_pin: OutputPin,
+    r_pin: Box<dyn PwmPin>,
+    g_pin: Box<dyn PwmPin>,
+    b_pin: Box<dyn PwmPin>,
     freq: f64,
 }
 
 impl RGBLed {
-    pub fn new(r_pin: u8, g_pin: u8, b_pin: u8) -> Result<Self, Error> {
-        let r = Gpio::new()?.get(r_pin)?.into_output_low();
-        let g = Gpio::new()?.get(g_pin)?.into_output_low();
-        let b = Gpio::new()?.get(b_pin)?.into_output_low();
+    pub fn new(hal: &dyn Hal, r_pin: u8, g_pin: u8, b_pin: u8) -> Result<Self, Error> {
+        let r = hal.pwm(r_pin)?;
+        let g = hal.pwm(g_pin)?;
+        let b = hal.pwm(b_pin)?;
         Ok(RGBLed {
             r_pin: r,
             g_pin: g,
@@ -1,17 +1,17 @@
 #![allow(unused)]
 use std::{thread, time::Duration};
 
-use rppal::gpio::{Error, Gpio, OutputPin};
+use crate::hal::{Error, Hal, PwmPin};
 
 pub struct Servo {
-    pulse_pin: OutputPin,
+    pulse_pin: Box<dyn PwmPin>,
     last_degree: i8,
 }
 
 impl Servo {
-    pub fn new(pulse_pin: u8) -> Result<Self, Error> {
+    pub fn new(hal: &dyn Hal, pulse_pin: u8) -> Result<Self, Error> {
         let mut s = Self {
-            pulse_pin: Gpio::new()?.get(pulse_pin)?.into_output_low(),
+            pulse_pin: hal.pwm(pulse_pin)?,
             last_degree: 0,
         };
         Ok(s)
@@ -12,6 +12,10 @@ use futures::StreamExt;
 use tokio::task::spawn_blocking;
 
 use crate::{
+    door::{
+        audit::Source,
+        door::{AutomationMode, Event},
+    },
     led::{frame::Frame, led::LED, stripe::PlayerColors},
     state::AppState,
     ws::messages::{ClientMsg, ServerMsg, WorkMode},
@@ -26,9 +30,18 @@ pub async fn ws_handler(
 async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
     let mut rx = state.tx.subscribe();
+    let door_status = ServerMsg::DoorState(state.door_monitor.status());
+    let door_stats = ServerMsg::DoorStats(state.stats.get());
+    let door_profile = ServerMsg::DoorProfile(state.profiles.status());
 
     // Task: Server → Client
     let send_task = tokio::spawn(async move {
+        // new clients should not wait for the next state change
+        for msg in [door_status, door_stats, door_profile] {
+            let text = serde_json::to_string(&msg).unwrap();
+            let _ = sender.send(Message::Text(Utf8Bytes::from(text))).await;
+        }
+
         while let Ok(msg) = rx.recv().await {
             let text = serde_json::to_string(&msg).unwrap();
             if sender
@@ -62,6 +75,39 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                 ClientMsg::LEDReset => {
                     led_reset(state.clone());
                 }
+                ClientMsg::DoorOpen { width_cm } => {
+                    let event = Event::Open { width_cm };
+                    match event.problem() {
+                        Some(problem) => println!("Door open refused: {}", problem),
+                        None => {
+                            state.door.send(event, Source::Ws);
+                        }
+                    }
+                }
+                ClientMsg::EmergencyStop => {
+                    state.door.send(Event::EmergencyStop, Source::Ws);
+                }
+                ClientMsg::DoorReset => {
+                    state.door.send(Event::Reset, Source::Ws);
+                }
+                ClientMsg::DoorProfile { name } => {
+                    if let Err(e) = state.profiles.switch(&name) {
+                        println!("Profile switch refused: {}", e);
+                    }
+                }
+                ClientMsg::DoorMotion { motion } => {
+                    if let Err(e) = state.profiles.set(motion) {
+                        println!("Motion profile refused: {}", e);
+                    }
+                }
+                ClientMsg::DoorAutomation { mode } => match mode {
+                    AutomationMode::Entry if !state.entry_radars => {
+                        println!("Entry automation refused: no radar in radar.entry_uarts");
+                        // puts the select of the client back
+                        state.door_monitor.notify();
+                    }
+                    _ => state.door_monitor.set_automation(mode),
+                },
                 ClientMsg::PlayerTable { p1, p2, p3 } => {
                     playertable(p1, p2, p3, state.clone());
                 }
@@ -1,13 +1,31 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{door::detector::Target, led::stripe::PlayerColors};
+use crate::{
+    config::MotionConfig,
+    door::{
+        audit::AuditEntry,
+        detector::Target,
+        door::AutomationMode,
+        monitor::{DoorFault, DoorNotice, DoorStatus},
+        profile::ProfileStatus,
+        stats::DoorStats,
+    },
+    led::stripe::PlayerColors,
+};
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum ServerMsg {
     StatusUpdate { value: String },
     PlaySound { name: String },
     Targets { id: u8, targets: [Target; 3] },
+    DoorState(DoorStatus),
+    DoorFault(DoorFault),
+    DoorNotice(DoorNotice),
+    DoorAudit(AuditEntry),
+    DoorStats(DoorStats),
+    DoorProfile(ProfileStatus),
 }
 
 #[derive(Deserialize, Debug)]
@@ -23,6 +41,21 @@ pub enum ClientMsg {
     },
     RedAlert,
     LEDReset,
+    DoorOpen {
+        width_cm: Option<f32>,
+    },
+    EmergencyStop,
+    DoorReset,
+    /// switches to a named motion profile
+    DoorProfile {
+        name: String,
+    },
+    DoorMotion {
+        motion: MotionConfig,
+    },
+    DoorAutomation {
+        mode: AutomationMode,
+    },
     PlayerTable {
         p1: PlayerColors,
         p2: PlayerColors,